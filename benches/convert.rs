@@ -0,0 +1,35 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use scrcap::platform::convert::create_converter;
+use scrcap::platform::FrameFormat;
+
+const WIDTH: usize = 3840;
+const HEIGHT: usize = 2160;
+
+fn convert(c: &mut Criterion) {
+    let data = (0..WIDTH * HEIGHT * 4)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>();
+
+    let mut group = c.benchmark_group("convert_inplace");
+    for format in [
+        FrameFormat::Xbgr2101010,
+        FrameFormat::Xrgb8888,
+        FrameFormat::Xbgr8888,
+        FrameFormat::Abgr2101010,
+        FrameFormat::Abgr8888,
+        FrameFormat::Argb8888,
+    ] {
+        let converter = create_converter(format);
+        group.bench_function(format!("{:?}", format), |b| {
+            b.iter_batched_ref(
+                || data.clone(),
+                |data| black_box(converter.convert_inplace(data)),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, convert);
+criterion_main!(benches);
@@ -0,0 +1,40 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use image::ColorType;
+use scrcap::output::{write_to_file, EncodingFormat};
+use scrcap::platform::{Frame, FrameDescription, FrameFormat};
+use std::io;
+
+const WIDTH: u32 = 3840;
+const HEIGHT: u32 = 2160;
+
+fn encode(c: &mut Criterion) {
+    let frame_format = FrameDescription {
+        format: FrameFormat::Xbgr8888,
+        width: WIDTH,
+        height: HEIGHT,
+        stride: WIDTH * 4,
+    };
+    let data = (0..WIDTH * HEIGHT * 4)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>();
+
+    let mut group = c.benchmark_group("write_to_file");
+    group.sample_size(10);
+    for encoding_format in [
+        EncodingFormat::Png,
+        EncodingFormat::Jpg,
+        EncodingFormat::Ppm,
+    ] {
+        group.bench_function(format!("{:?}", encoding_format), |b| {
+            b.iter_batched(
+                || Frame::from_bytes(frame_format, ColorType::Rgba8, &data).unwrap(),
+                |frame| write_to_file(io::sink(), encoding_format, frame).unwrap(),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, encode);
+criterion_main!(benches);
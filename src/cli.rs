@@ -0,0 +1,1550 @@
+use chrono::{Local, NaiveDateTime, NaiveTime};
+use clap::Parser;
+
+use std::env;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
+use crate::error::ScrcapError;
+use crate::output::{
+    diff_images, get_screenshot_directory, load_baseline, load_watermark, render_terminal_preview,
+    terminal_columns, write_buffer_to_file, write_capture_json, write_raw, write_raw_description,
+    write_to_file, write_to_file_with_options, CaptureReport, ColorProfile, EncodingFormat,
+    EncodingOptions, PngCompression, PostProcess, Watermark,
+};
+use crate::platform::{
+    output_at_point, region_placement_error, Frame, FrameFormat, Output, OutputTransform, Platform,
+    Region, Timings,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::ColorType;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+/// Error for a compositor that reports no outputs, which happens if all of them are turned off
+const NO_OUTPUTS_ERROR: &str = "No output found! Are all outputs turned off or asleep?";
+
+/// Shutter sound of the freedesktop sound theme
+const DEFAULT_SHUTTER_SOUND: &str = "/usr/share/sounds/freedesktop/stereo/camera-shutter.oga";
+
+#[derive(Parser, Debug, Clone)]
+#[command(author, version, about, long_about = None)]
+/// A screenshot tool written in Rust
+pub struct CmdArgs {
+    /// Filename to use for screenshot. A known file extension selects the encoding format. If
+    /// it names a FIFO, the encoded screenshot gets streamed into it
+    #[arg(short, long)]
+    filename: Option<String>,
+    /// Directory where the screenshot will be saved. Defaults to $SCRCAP_DIR, the pictures
+    /// directory, the home directory or the current directory, in that order
+    #[arg(short, long)]
+    directory: Option<String>,
+    /// Format to use for encoding screenshot (png, png16, jpg, ppm). png16 keeps the full
+    /// precision of 10-bit outputs, but can only capture regions that lie on a single output
+    #[arg(short, long)]
+    encoding_format: Option<EncodingFormat>,
+    /// X coordinate for screenshot region. Relative to the window or workspace if --active,
+    /// --wait-for-window, --workspace or --mark is set
+    #[arg(short, long)]
+    x: Option<i32>,
+    /// Y coordinate for screenshot region. Relative to the window or workspace if --active,
+    /// --wait-for-window, --workspace or --mark is set
+    #[arg(short, long)]
+    y: Option<i32>,
+    /// Width for screenshot region
+    #[arg(short, long)]
+    width: Option<i32>,
+    /// Height for screenshot region. The short flag is -H, as -h prints the help
+    #[arg(short = 'H', long)]
+    height: Option<i32>,
+    /// Interpret -x, -y, -w and -H as physical pixels of the output, as in a screenshot of the
+    /// whole output, instead of global logical coordinates
+    #[arg(long, conflicts_with_all = ["active", "wait_for_window", "workspace", "mark", "preset"])]
+    physical: bool,
+    /// Encode JPEG images progressively, which is nicer for the web. Needs scrcap to be built
+    /// with the progressive-jpeg feature
+    #[arg(long)]
+    progressive: bool,
+    /// Quality of JPEG images from 1 to 100. Defaults to the config file or 75
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    jpeg_quality: Option<u8>,
+    /// Compression of PNG images (fast, default, best). Defaults to the config file or default
+    #[arg(long)]
+    png_compression: Option<PngCompression>,
+    /// Tag PNG images with a color profile, either srgb or the path of an ICC profile to embed
+    #[arg(long)]
+    color_profile: Option<ColorProfile>,
+    /// Write PPM images as ASCII (P3) instead of binary (P6), e.g. to diff the pixels as text
+    #[arg(long)]
+    ppm_ascii: bool,
+    /// Store the version of scrcap, the compositor and the Wayland socket in PNG images, which
+    /// helps when reporting bugs with a screenshot
+    #[arg(long)]
+    metadata: bool,
+    /// Fail if the compositor does not offer this frame format, e.g. xbgr2101010 to make sure
+    /// that 10-bit data gets captured
+    #[arg(long)]
+    require_format: Option<FrameFormat>,
+    /// Constrain the region to an aspect ratio, e.g. 16:9. The height is derived from the width
+    /// or vice versa if only one of them is given, otherwise the region shrinks to fit
+    #[arg(long)]
+    aspect: Option<AspectRatio>,
+    /// Capture a region preset of the config file. Its coordinates are relative to the output
+    #[arg(long, conflicts_with_all = ["x", "y", "width", "height", "aspect", "active", "wait_for_window", "workspace", "mark"])]
+    preset: Option<String>,
+    /// List the region presets of the config file resolved on the output and exit
+    #[arg(long)]
+    list_presets: bool,
+    /// Make a screenshot of the active window
+    #[arg(short, long)]
+    active: bool,
+    /// Name the screenshot after the app id or title of the active window
+    #[arg(long, requires = "active", conflicts_with = "filename")]
+    name_from_window: bool,
+    /// Wait for a window with this app_id or a title containing it and make a screenshot of it
+    #[arg(long, conflicts_with = "active")]
+    wait_for_window: Option<String>,
+    /// Make a screenshot of the visible workspace with this name or number
+    #[arg(long, conflicts_with_all = ["active", "wait_for_window"])]
+    workspace: Option<String>,
+    /// Make a screenshot of the window carrying this sway mark
+    #[arg(long, conflicts_with_all = ["active", "wait_for_window", "workspace"])]
+    mark: Option<String>,
+    /// Seconds to wait for the window of --wait-for-window
+    #[arg(long, default_value_t = 10)]
+    wait_timeout: u64,
+    /// Name of the output to screenshot. E.g. DP-1, eDP-1. Several comma separated outputs get
+    /// saved to one file each, named after the output. Defaults to the focused output
+    #[arg(short, long)]
+    output_name: Option<String>,
+    /// Capture every output matching a condition like `scale>=2` or `width>=3840` into its own
+    /// file, named after the output. Properties are name, x, y, width, height and scale
+    #[arg(long, conflicts_with_all = ["output_name", "pick_output"])]
+    outputs_where: Option<OutputFilter>,
+    /// Choose the output to screenshot from a list in the terminal
+    #[arg(long, conflicts_with = "output_name")]
+    pick_output: bool,
+    /// Play a shutter sound after the screenshot was taken. Needs paplay
+    #[arg(long)]
+    sound: bool,
+    /// Sound file to play for --sound
+    #[arg(long, default_value = DEFAULT_SHUTTER_SOUND, requires = "sound")]
+    sound_file: String,
+    /// Print the screenshot as base64 data URI to stdout instead of saving it
+    #[arg(long)]
+    data_uri: bool,
+    /// Print the path, size, format and file size of the saved screenshot as JSON to stdout
+    #[arg(long, conflicts_with_all = ["data_uri", "preview_term", "raw", "since_last_change"])]
+    json: bool,
+    /// Add the milliseconds spent connecting, discovering outputs, capturing, converting and
+    /// encoding to the JSON output
+    #[arg(long, requires = "json")]
+    timing: bool,
+    /// Compare the screenshot with a baseline image instead of saving it and print how many
+    /// pixels differ. Fails if more than --diff-threshold percent of them differ
+    #[arg(long, conflicts_with_all = ["data_uri", "json", "raw", "since_last_change"])]
+    diff: Option<PathBuf>,
+    /// Percentage of pixels that may differ from the --diff baseline
+    #[arg(long, default_value_t = 0.0, value_parser = parse_percentage, requires = "diff")]
+    diff_threshold: f64,
+    /// Save an image of the --diff comparison as PNG, with the differing pixels in red
+    #[arg(long, requires = "diff")]
+    diff_output: Option<PathBuf>,
+    /// Print an ASCII preview of the screenshot to the terminal. Nothing gets saved unless a
+    /// filename is given too
+    #[arg(long)]
+    preview_term: bool,
+    /// Put a logo onto the screenshot, given as PATH[:POSITION]. The position is one of
+    /// top-left, top-right, bottom-left and bottom-right, which is the default
+    #[arg(long)]
+    watermark: Option<Watermark>,
+    /// Rotate and flip the screenshot as if the output had the given transform, for outputs
+    /// whose transform is reported wrong. One of normal, 90, 180, 270, flipped, flipped-90,
+    /// flipped-180 and flipped-270, clockwise
+    #[arg(long)]
+    output_transform: Option<OutputTransform>,
+    /// Cut off uniform borders around the content, e.g. the wallpaper around a window. The
+    /// color of the top left pixel is the border color
+    #[arg(long)]
+    crop_to_content: bool,
+    /// Maximum difference of a color channel to the border color for --crop-to-content
+    #[arg(long, default_value_t = 8, requires = "crop_to_content")]
+    crop_tolerance: u8,
+    /// Additionally save a thumbnail with the given width next to the screenshot
+    #[arg(long)]
+    thumbnail: Option<u32>,
+    /// When capturing a region or window, additionally save the whole output containing it to
+    /// a file with -full appended to the filename
+    #[arg(long, conflicts_with_all = ["data_uri", "raw", "since_last_change", "diff"])]
+    also_full: bool,
+    /// Save the screenshot to a temporary file, or into --directory if given, and run the
+    /// command on it. `{}` gets replaced with the path of the file. E.g. 'swappy -f {}'
+    #[arg(long)]
+    exec: Option<String>,
+    /// Keep the temporary file after the command of --exec finished
+    #[arg(long, requires = "exec")]
+    exec_keep: bool,
+    /// Save the unconverted pixel data as sent by the compositor to a .raw file, together with a
+    /// .json file that describes its layout
+    #[arg(long, conflicts_with_all = ["data_uri", "preview_term", "thumbnail", "exec", "watermark", "output_transform", "crop_to_content"])]
+    raw: bool,
+    /// Wait until the given time of day, given as HH:MM or HH:MM:SS, before capturing. Times
+    /// that already passed today are scheduled for tomorrow
+    #[arg(long, value_parser = parse_time_of_day)]
+    at: Option<NaiveTime>,
+    /// Wait for the given duration before capturing, e.g. 90s, 30m or 1h30m
+    #[arg(long = "in", value_parser = parse_duration, conflicts_with = "at")]
+    in_duration: Option<Duration>,
+    /// Read regions given as `X,Y WxH` from stdin, one per line, and save a screenshot of each
+    /// until the end of the input. The files are numbered in the order of the lines
+    #[arg(long, conflicts_with_all = ["x", "y", "width", "height", "aspect", "active", "wait_for_window", "workspace", "mark", "preset", "physical", "pick_output", "outputs_where", "repeat"])]
+    region_stdin: bool,
+    /// Keep running and save a timestamped screenshot whenever the output changed, at most once
+    /// per the given number of milliseconds
+    #[arg(long, value_name = "MS", value_parser = clap::value_parser!(u64).range(1..), conflicts_with_all = ["raw", "data_uri", "preview_term", "exec", "repeat"])]
+    since_last_change: Option<u64>,
+    /// Stop recording changes after this many screenshots
+    #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u32).range(1..), requires = "since_last_change")]
+    max_files: u32,
+    /// Repeat the last capture. Explicitly given arguments override the stored ones
+    #[arg(short, long)]
+    repeat: bool,
+    /// Report the compositor, its supported protocols and the outputs instead of capturing
+    #[arg(long)]
+    doctor: bool,
+    /// Print the version together with the negotiated Wayland protocol versions and whether
+    /// the sway IPC is available
+    #[arg(long, conflicts_with = "doctor")]
+    version_verbose: bool,
+    /// Log every raw wl_output and xdg_output event received while discovering the outputs
+    #[arg(long)]
+    verbose_outputs: bool,
+    /// Log the stride of every buffer advertised by the compositor next to the stride of
+    /// packed rows, to spot buffers with aligned rows
+    #[arg(long)]
+    debug_stride: bool,
+}
+
+impl CmdArgs {
+    /// Whether only the diagnostics report was requested
+    pub fn doctor(&self) -> bool {
+        self.doctor
+    }
+
+    /// Whether only the version and the negotiated protocol versions were requested
+    pub fn version_verbose(&self) -> bool {
+        self.version_verbose
+    }
+
+    /// Whether the arguments take a screenshot, instead of only reporting or listing something.
+    /// Only captures are remembered for --repeat.
+    pub fn is_capture(&self) -> bool {
+        !(self.doctor || self.version_verbose || self.list_presets)
+    }
+
+    /// Whether the arguments of the last capture should be repeated
+    pub fn repeat(&self) -> bool {
+        self.repeat
+    }
+
+    /// Whether the raw output discovery events should be logged
+    pub fn verbose_outputs(&self) -> bool {
+        self.verbose_outputs
+    }
+
+    /// Whether the advertised and the expected stride of buffers should be logged
+    pub fn debug_stride(&self) -> bool {
+        self.debug_stride
+    }
+}
+
+/// Aspect ratio of a region given as `W:H`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AspectRatio {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl FromStr for AspectRatio {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let Some((width, height)) = value.split_once(':') else {
+            bail!("Aspect ratio must be given as W:H, e.g. 16:9");
+        };
+        let width = width.trim().parse::<i32>()?;
+        let height = height.trim().parse::<i32>()?;
+        if width <= 0 || height <= 0 {
+            bail!("Aspect ratio must be positive");
+        }
+
+        Ok(Self { width, height })
+    }
+}
+
+impl AspectRatio {
+    /// Adjust the size to the aspect ratio. An explicitly given width or height is kept if the
+    /// other one is derived, otherwise the size shrinks until it fits.
+    pub fn constrain(
+        &self,
+        width: i32,
+        height: i32,
+        width_given: bool,
+        height_given: bool,
+    ) -> (i32, i32) {
+        let (ratio_width, ratio_height) = (self.width as i64, self.height as i64);
+        let height_from_width = (width as i64 * ratio_height / ratio_width) as i32;
+        let width_from_height = (height as i64 * ratio_width / ratio_height) as i32;
+
+        match (width_given, height_given) {
+            (true, false) => (width, height_from_width),
+            (false, true) => (width_from_height, height),
+            _ if height_from_width > height => (width_from_height, height),
+            _ => (width, height_from_width),
+        }
+    }
+}
+
+/// Property of an output that can be compared in an `OutputFilter`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputProperty {
+    Name,
+    X,
+    Y,
+    Width,
+    Height,
+    Scale,
+}
+
+/// Comparison operator of an `OutputFilter`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// Value an output property is compared with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterValue {
+    Name(String),
+    Number(i32),
+}
+
+/// Condition on a property of an output given as `PROPERTY OPERATOR VALUE`, e.g. `scale>=2`.
+/// The name can only be compared with `=` and `!=`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputFilter {
+    pub property: OutputProperty,
+    pub comparison: Comparison,
+    pub value: FilterValue,
+}
+
+impl FromStr for OutputFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let Some(operator_start) = value.find(['<', '>', '=', '!']) else {
+            bail!("Output filter must be given as PROPERTY OPERATOR VALUE, e.g. scale>=2");
+        };
+        let (property, rest) = value.split_at(operator_start);
+        let (comparison, operator) = [
+            (Comparison::GreaterOrEqual, ">="),
+            (Comparison::LessOrEqual, "<="),
+            (Comparison::NotEqual, "!="),
+            (Comparison::Equal, "=="),
+            (Comparison::Equal, "="),
+            (Comparison::Greater, ">"),
+            (Comparison::Less, "<"),
+        ]
+        .into_iter()
+        .find(|(_, operator)| rest.starts_with(operator))
+        .ok_or_else(|| anyhow!("Unknown operator in output filter {:?}", value))?;
+        let filter_value = rest[operator.len()..].trim();
+
+        let property = match property.trim() {
+            "name" => OutputProperty::Name,
+            "x" => OutputProperty::X,
+            "y" => OutputProperty::Y,
+            "width" => OutputProperty::Width,
+            "height" => OutputProperty::Height,
+            "scale" => OutputProperty::Scale,
+            property => bail!(
+                "Unknown output property {:?}, expected one of name, x, y, width, height, scale",
+                property
+            ),
+        };
+        let value = if property == OutputProperty::Name {
+            if !matches!(comparison, Comparison::Equal | Comparison::NotEqual) {
+                bail!("Output names can only be compared with = and !=");
+            }
+            FilterValue::Name(filter_value.into())
+        } else {
+            let number = filter_value.parse().with_context(|| {
+                format!("Output filter value {:?} is not a number", filter_value)
+            })?;
+            FilterValue::Number(number)
+        };
+
+        Ok(Self {
+            property,
+            comparison,
+            value,
+        })
+    }
+}
+
+impl OutputFilter {
+    /// Check if the output fulfills the condition
+    pub fn matches(&self, output: &Output) -> bool {
+        let ordering = match &self.value {
+            FilterValue::Name(name) => output.name.cmp(name),
+            FilterValue::Number(number) => {
+                let actual = match self.property {
+                    // Parsing only pairs the name with a name value
+                    OutputProperty::Name => return false,
+                    OutputProperty::X => output.x,
+                    OutputProperty::Y => output.y,
+                    OutputProperty::Width => output.width,
+                    OutputProperty::Height => output.height,
+                    OutputProperty::Scale => output.scale,
+                };
+                actual.cmp(number)
+            }
+        };
+
+        match self.comparison {
+            Comparison::Equal => ordering.is_eq(),
+            Comparison::NotEqual => ordering.is_ne(),
+            Comparison::Less => ordering.is_lt(),
+            Comparison::LessOrEqual => ordering.is_le(),
+            Comparison::Greater => ordering.is_gt(),
+            Comparison::GreaterOrEqual => ordering.is_ge(),
+        }
+    }
+}
+
+/// Output, region and format arguments of the last capture. Used by --repeat.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LastCapture {
+    pub output_name: Option<String>,
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub active: bool,
+    pub physical: bool,
+    pub encoding_format: Option<EncodingFormat>,
+}
+
+impl LastCapture {
+    pub fn from_args(args: &CmdArgs) -> Self {
+        Self {
+            output_name: args.output_name.clone(),
+            x: args.x,
+            y: args.y,
+            width: args.width,
+            height: args.height,
+            active: args.active,
+            physical: args.physical,
+            encoding_format: args.encoding_format,
+        }
+    }
+
+    /// Fill all arguments that were not given explicitly with the stored ones
+    pub fn apply(&self, args: &CmdArgs) -> CmdArgs {
+        let mut args = args.clone();
+        args.output_name = args.output_name.or_else(|| self.output_name.clone());
+        args.x = args.x.or(self.x);
+        args.y = args.y.or(self.y);
+        args.width = args.width.or(self.width);
+        args.height = args.height.or(self.height);
+        args.active |= self.active;
+        args.physical |= self.physical;
+        args.encoding_format = args.encoding_format.or(self.encoding_format);
+        args
+    }
+
+    pub fn parse(data: &str) -> Result<Self> {
+        Ok(toml::from_str(data)?)
+    }
+
+    /// Location of the state file holding the last capture
+    pub fn path() -> Result<PathBuf> {
+        let Some(state_dir) = dirs::state_dir().or_else(dirs::cache_dir) else {
+            bail!("Could not find a directory for the last capture");
+        };
+        Ok(state_dir.join("scrcap").join("last-capture"))
+    }
+
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        let data = fs::read_to_string(&path)
+            .with_context(|| format!("Could not read last capture from {:?}", path))?;
+        Self::parse(&data).with_context(|| format!("Invalid last capture {:?}", path))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, toml::to_string(self)?)
+            .with_context(|| format!("Could not write last capture to {:?}", path))
+    }
+}
+
+/// Take a screenshot on the given platform as described by the command line arguments and the
+/// config. The arguments of --repeat have to be filled in by the caller with
+/// `LastCapture::apply`.
+pub fn run(args: &CmdArgs, config: &Config, platform: &mut dyn Platform) -> Result<()> {
+    // Wait for the scheduled time
+    let scheduled_wait = match (args.at, args.in_duration) {
+        (Some(at), _) => Some(duration_until(at, Local::now().naive_local())),
+        (None, in_duration) => in_duration,
+    };
+    if let Some(scheduled_wait) = scheduled_wait {
+        info!("Capture in {}s", scheduled_wait.as_secs());
+        thread::sleep(scheduled_wait);
+    }
+
+    // Capture every region read from stdin into its own file
+    if args.region_stdin {
+        return run_region_stream(args, config, io::stdin().lock(), platform);
+    }
+
+    // Capture every output matching the filter into its own file
+    if let Some(filter) = args.outputs_where.as_ref() {
+        let output_names = platform
+            .outputs()
+            .into_iter()
+            .filter(|output| filter.matches(output))
+            .map(|output| output.name)
+            .collect::<Vec<_>>();
+        if output_names.is_empty() {
+            bail!(ScrcapError::NoOutput("No output matches the filter".into()));
+        }
+        return run_for_outputs(args, config, &output_names.join(","), platform);
+    }
+
+    // Capture every output of a comma separated list into its own file
+    if let Some(output_names) = args
+        .output_name
+        .as_ref()
+        .filter(|names| names.contains(','))
+    {
+        return run_for_outputs(args, config, output_names, platform);
+    }
+
+    // Get filename
+    let filename = match args.filename.clone() {
+        Some(filename) => filename,
+        None => default_filename(args, platform)?,
+    };
+
+    // Get encoding that should be used for screenshot. A known extension of the filename
+    // selects the format if none was given and gets stripped, as it is appended again on save.
+    let (filename, image_encoding) = split_filename_extension(filename, args.encoding_format);
+
+    // Get the directory where the screenshot should be saved
+    let directory = if let Some(directory) = args.directory.as_ref() {
+        directory.clone()
+    } else if args.exec.is_some() {
+        env::temp_dir().to_string_lossy().into()
+    } else {
+        get_screenshot_directory().context("Could not get a writeable directory for screenshot")?
+    };
+
+    // Take the screenshot
+    platform.set_required_format(args.require_format);
+    let outputs = platform.outputs();
+    if outputs.is_empty() {
+        warn!("The compositor reported no outputs, all of them may be turned off (DPMS)");
+    }
+
+    // Find output by name or let the user pick it if needed
+    let output = if args.pick_output {
+        pick_output(&outputs, io::stdin().lock(), io::stderr())?
+    } else if args.output_name.is_none() {
+        default_output(platform, &outputs)?
+    } else {
+        get_output(args.output_name.clone(), &outputs)?
+    };
+
+    if args.list_presets {
+        return write_presets(config, output, io::stdout().lock());
+    }
+
+    // Get region on which screenshot should be captured
+    let region = if let Some(preset) = args.preset.as_ref() {
+        Some(config.preset(preset, output)?)
+    } else if args.active
+        || args.wait_for_window.is_some()
+        || args.workspace.is_some()
+        || args.mark.is_some()
+    {
+        let window = if let Some(name) = args.wait_for_window.as_ref() {
+            platform.wait_for_window(name, Duration::from_secs(args.wait_timeout))?
+        } else if let Some(name) = args.workspace.as_ref() {
+            platform.workspace_area(name)?
+        } else if let Some(mark) = args.mark.as_ref() {
+            platform.marked_window_area(mark)?
+        } else {
+            platform.focused_window_area()?
+        };
+        if let Some(region) = get_window_region_from_args(args, window) {
+            Some(region?)
+        } else {
+            Some(window)
+        }
+    } else if let Some(region) = get_region_from_args(args, output) {
+        Some(region?)
+    } else {
+        // Fall back to the region configured for the output
+        config.default_region(output)?
+    };
+
+    let encoding_options = EncodingOptions {
+        progressive: args.progressive,
+        color_profile: args.color_profile.clone(),
+        jpeg_quality: args.jpeg_quality.or(config.encoding.jpeg_quality),
+        png_compression: args.png_compression.or(config.encoding.png_compression),
+        // The density of the output the capture lies on, unknown if it spans outputs
+        pixels_per_meter: match region {
+            Some(region) => outputs
+                .iter()
+                .find(|output| output.region().contains(region))
+                .and_then(Output::pixels_per_meter),
+            None => output.pixels_per_meter(),
+        },
+        ppm_ascii: args.ppm_ascii,
+        metadata: if args.metadata {
+            platform.session_info().metadata()
+        } else {
+            Vec::new()
+        },
+    };
+
+    let post_process = PostProcess {
+        transform: args.output_transform,
+        crop_to_content: args.crop_to_content.then_some(args.crop_tolerance),
+        watermark: match args.watermark.as_ref() {
+            Some(watermark) => Some((load_watermark(watermark)?, watermark.position)),
+            None => None,
+        },
+    };
+
+    if let Some(interval) = args.since_last_change {
+        // Damage is reported per output, so the region must be on one output
+        let output = match region {
+            Some(region) => find_output_from_region(region, &outputs)?,
+            None => output,
+        };
+        let recording = ChangeRecording {
+            output,
+            region,
+            interval: Duration::from_millis(interval),
+            max_files: args.max_files,
+            directory: &directory,
+            filename: &filename,
+            encoding: image_encoding,
+            options: &encoding_options,
+            post_process: &post_process,
+        };
+        return recording.run(platform);
+    }
+
+    if args.raw {
+        // Raw frames can not be composed, so the region must be on one output
+        let output = match region {
+            Some(region) => find_output_from_region(region, &outputs)?,
+            None => output,
+        };
+        let frame = platform.capture_frame_raw(output, false, region)?;
+
+        let path = format!("{}/{}.raw", directory, filename);
+        let description_path = format!("{}/{}.json", directory, filename);
+        debug!("Write raw screenshot to {}", path);
+        File::create(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| write_raw(file, &frame))
+            .with_context(|| write_failed(&path))?;
+        File::create(&description_path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| write_raw_description(file, &frame))
+            .with_context(|| write_failed(&description_path))?;
+        info!(
+            "Saved {}x{} raw screenshot ({:?}, stride {}) to {}",
+            frame.frame_format.width,
+            frame.frame_format.height,
+            frame.frame_format.format,
+            frame.frame_format.stride,
+            path
+        );
+        return Ok(());
+    }
+
+    // Capture the region on the outputs it belongs to or the whole output
+    let frame = if image_encoding == EncodingFormat::Png16 {
+        // Keeping the depth needs the unconverted frame, which can not be composed
+        let output = match region {
+            Some(region) => find_output_from_region(region, &outputs)
+                .map_err(|err| png16_region_error(region, &outputs).unwrap_or(err))?,
+            None => output,
+        };
+        platform
+            .capture_frame_raw(output, false, region)?
+            .to_rgba16()?
+    } else if let Some(region) = region {
+        debug!("Take screenshot of region {:?}", region);
+        platform.capture_region_global(region, false)?
+    } else {
+        debug!("Take screenshot on output {:?}", output);
+        platform.capture_frame(output, false, None)?
+    };
+
+    // Capture the output containing the region right after it, to show the context
+    let full_frame = match region {
+        Some(region) if args.also_full => {
+            let (x, y) = (region.x + region.width / 2, region.y + region.height / 2);
+            let full_output = output_at_point(&outputs, x, y).unwrap_or(output);
+            debug!("Take screenshot of the whole output {:?}", full_output);
+            let full_frame = if image_encoding == EncodingFormat::Png16 {
+                platform
+                    .capture_frame_raw(full_output, false, None)?
+                    .to_rgba16()?
+            } else {
+                platform.capture_frame(full_output, false, None)?
+            };
+            Some(post_process.apply(full_frame)?)
+        }
+        None if args.also_full => {
+            warn!("--also-full only has an effect when capturing a region or window");
+            None
+        }
+        _ => None,
+    };
+
+    let frame = post_process.apply(frame)?;
+
+    if let Some(baseline) = args.diff.as_ref() {
+        return compare_with_baseline(args, &frame, baseline);
+    }
+
+    if args.sound {
+        play_sound(&args.sound_file);
+    }
+
+    if args.preview_term {
+        print!("{}", render_terminal_preview(&frame, terminal_columns())?);
+        if args.filename.is_none() {
+            return Ok(());
+        }
+    }
+
+    if args.data_uri {
+        let mut buffer = Vec::new();
+        write_to_file_with_options(&mut buffer, image_encoding, frame, encoding_options)?;
+        println!(
+            "data:{};base64,{}",
+            image_encoding.mime_type(),
+            STANDARD.encode(buffer)
+        );
+        return Ok(());
+    }
+
+    // Stream the screenshot into a FIFO as it is, without appending an extension
+    if let Some(fifo) = args
+        .filename
+        .as_deref()
+        .and_then(|filename| find_fifo(filename, &directory))
+    {
+        debug!("Write screenshot to FIFO {}", fifo);
+        OpenOptions::new()
+            .write(true)
+            .open(&fifo)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| {
+                write_to_file_with_options(file, image_encoding, frame, encoding_options)
+            })
+            .with_context(|| write_failed(&fifo))?;
+        info!("Streamed screenshot to {}", fifo);
+        return Ok(());
+    }
+
+    // Write screenshot to disk
+    let path = format!("{}/{}.{}", directory, filename, image_encoding.extension());
+    let thumbnail = args
+        .thumbnail
+        .map(|width| frame.resize_to_width(width))
+        .transpose()?;
+
+    debug!("Write screenshot to {}", path);
+    let frame_format = frame.frame_format;
+    let encode_start = Instant::now();
+    File::create(&path)
+        .map_err(anyhow::Error::from)
+        .and_then(|file| {
+            write_to_file_with_options(file, image_encoding, frame, encoding_options.clone())
+        })
+        .with_context(|| write_failed(&path))?;
+    let encode_time = encode_start.elapsed();
+
+    // Report what was written
+    let file_size = fs::metadata(&path)?.len();
+    info!(
+        "Saved {}x{} screenshot ({:?}, {} bytes) to {}",
+        frame_format.width, frame_format.height, frame_format.format, file_size, path
+    );
+    if args.json {
+        let report = CaptureReport {
+            path: path.clone(),
+            width: frame_format.width,
+            height: frame_format.height,
+            format: frame_format.format,
+            bytes: file_size,
+            timings: args.timing.then(|| Timings {
+                encode: encode_time,
+                ..platform.timings()
+            }),
+        };
+        write_capture_json(io::stdout().lock(), &report)?;
+    }
+
+    if let Some(thumbnail) = thumbnail {
+        let thumbnail_path = format!("{}/{}.thumb.png", directory, filename);
+        debug!("Write thumbnail to {}", thumbnail_path);
+        File::create(&thumbnail_path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| write_to_file(file, EncodingFormat::Png, thumbnail))
+            .with_context(|| write_failed(&thumbnail_path))?;
+    }
+
+    if let Some(full_frame) = full_frame {
+        let full_path = format!(
+            "{}/{}-full.{}",
+            directory,
+            filename,
+            image_encoding.extension()
+        );
+        debug!("Write screenshot of the whole output to {}", full_path);
+        File::create(&full_path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| {
+                write_to_file_with_options(file, image_encoding, full_frame, encoding_options)
+            })
+            .with_context(|| write_failed(&full_path))?;
+        info!("Saved screenshot of the whole output to {}", full_path);
+    }
+
+    if let Some(command) = args.exec.as_ref() {
+        run_command_on_file(command, &path)?;
+        if !args.exec_keep {
+            fs::remove_file(&path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Error for a png16 capture of a region that spans several outputs, `None` if it does not
+fn png16_region_error(region: Region, outputs: &[Output]) -> Option<anyhow::Error> {
+    let spanned_outputs = outputs
+        .iter()
+        .filter(|output| output.region().intersects(region))
+        .map(|output| output.name.as_str())
+        .collect::<Vec<_>>();
+    if spanned_outputs.len() < 2 {
+        return None;
+    }
+    Some(anyhow!(ScrcapError::InvalidRegion(format!(
+        "Region {} spans the outputs {}. png16 keeps the depth of the frames, so it can not \
+         combine them and only captures regions on a single output. Use png for this region",
+        region,
+        spanned_outputs.join(", ")
+    ))))
+}
+
+/// Compare the screenshot with the baseline of --diff and save the diff image if requested.
+/// Fails if the difference exceeds the threshold.
+fn compare_with_baseline(args: &CmdArgs, frame: &Frame, baseline: &Path) -> Result<()> {
+    let diff = diff_images(&frame.to_rgba_image()?, &load_baseline(baseline)?)?;
+    println!(
+        "{} of {} pixels differ ({:.2}%)",
+        diff.differing_pixels,
+        diff.total_pixels,
+        diff.percentage()
+    );
+
+    if let Some(diff_output) = args.diff_output.as_ref() {
+        let path = diff_output.to_string_lossy();
+        debug!("Write diff image to {}", path);
+        File::create(diff_output)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| {
+                write_buffer_to_file(
+                    file,
+                    &diff.diff_image,
+                    diff.diff_image.width(),
+                    diff.diff_image.height(),
+                    ColorType::Rgba8,
+                    EncodingFormat::Png,
+                    EncodingOptions::default(),
+                )
+            })
+            .with_context(|| write_failed(&path))?;
+    }
+
+    if diff.percentage() > args.diff_threshold {
+        bail!(ScrcapError::DiffExceeded(format!(
+            "{:.2}% of the pixels differ from the baseline, at most {}% may differ",
+            diff.percentage(),
+            args.diff_threshold
+        )));
+    }
+    Ok(())
+}
+
+/// Parse a percentage from 0 to 100
+fn parse_percentage(value: &str) -> Result<f64> {
+    match value.parse::<f64>() {
+        Ok(percentage) if (0.0..=100.0).contains(&percentage) => Ok(percentage),
+        _ => bail!("Percentage must be a number from 0 to 100"),
+    }
+}
+
+/// Parse a time of day given as HH:MM or HH:MM:SS
+fn parse_time_of_day(value: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(value, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M"))
+        .map_err(|_| anyhow!("Time must be given as HH:MM or HH:MM:SS"))
+}
+
+/// Parse a duration given as a sequence of numbers with the units h, m or s, e.g. 1h30m
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    let mut seconds = 0;
+    let mut number = String::new();
+    for character in value.trim().chars() {
+        if character.is_ascii_digit() {
+            number.push(character);
+            continue;
+        }
+        let unit = match character {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => bail!("Unknown unit {} in duration, use h, m or s", character),
+        };
+        let Ok(amount) = number.parse::<u64>() else {
+            bail!("Duration must be given as numbers with units, e.g. 90s, 30m or 1h30m");
+        };
+        seconds += amount * unit;
+        number.clear();
+    }
+    if !number.is_empty() || value.trim().is_empty() {
+        bail!("Duration must be given as numbers with units, e.g. 90s, 30m or 1h30m");
+    }
+
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Time from now until the next occurrence of the time of day
+pub fn duration_until(time: NaiveTime, now: NaiveDateTime) -> Duration {
+    let mut scheduled = now.date().and_time(time);
+    if scheduled < now {
+        scheduled += chrono::Duration::days(1);
+    }
+    (scheduled - now).to_std().unwrap_or_default()
+}
+
+/// Counters of the screenshots saved during a session, printed when it ends
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SessionStats {
+    pub captures: u32,
+    pub bytes_written: u64,
+    /// Time spent encoding and writing all screenshots
+    pub encode_time: Duration,
+    /// Number of screenshots per format, in the order the formats were first used
+    pub formats: Vec<(EncodingFormat, u32)>,
+}
+
+impl SessionStats {
+    /// Count a saved screenshot
+    pub fn record(&mut self, format: EncodingFormat, bytes: u64, encode_time: Duration) {
+        self.captures += 1;
+        self.bytes_written += bytes;
+        self.encode_time += encode_time;
+        match self.formats.iter_mut().find(|(known, _)| *known == format) {
+            Some((_, count)) => *count += 1,
+            None => self.formats.push((format, 1)),
+        }
+    }
+
+    pub fn average_encode_time(&self) -> Duration {
+        match self.captures {
+            0 => Duration::ZERO,
+            captures => self.encode_time / captures,
+        }
+    }
+}
+
+impl fmt::Display for SessionStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Captures: {}", self.captures)?;
+        writeln!(f, "Bytes written: {}", self.bytes_written)?;
+        writeln!(f, "Average encode time: {:?}", self.average_encode_time())?;
+        let formats: Vec<String> = self
+            .formats
+            .iter()
+            .map(|(format, count)| {
+                let name: String = (*format).into();
+                format!("{} {}", name, count)
+            })
+            .collect();
+        writeln!(f, "Formats: {}", formats.join(", "))
+    }
+}
+
+/// Settings of a recording that saves a screenshot whenever the output changed
+struct ChangeRecording<'a> {
+    output: &'a Output,
+    region: Option<Region>,
+    /// Minimum time between two screenshots
+    interval: Duration,
+    max_files: u32,
+    directory: &'a str,
+    filename: &'a str,
+    encoding: EncodingFormat,
+    options: &'a EncodingOptions,
+    post_process: &'a PostProcess,
+}
+
+impl ChangeRecording<'_> {
+    /// Save screenshots until `max_files` were written and print the stats of the session, also
+    /// if it ended with an error.
+    fn run(&self, platform: &mut dyn Platform) -> Result<()> {
+        let mut stats = SessionStats::default();
+        let result = self.record(platform, &mut stats);
+        eprint!("{}", stats);
+        result
+    }
+
+    /// Save screenshots until `max_files` were written. Changes within the interval after a
+    /// screenshot are combined, the screenshot at the end of the interval shows all of them.
+    fn record(&self, platform: &mut dyn Platform, stats: &mut SessionStats) -> Result<()> {
+        let mut last_save: Option<Instant> = None;
+        for _ in 0..self.max_files {
+            let frame = platform.capture_frame_with_damage(self.output, false, self.region)?;
+            debug!("Output changed in {:?}", frame.damage);
+
+            // Wait for the rest of the interval and capture the state at its end
+            let remaining = last_save.and_then(|last_save| {
+                self.interval
+                    .checked_sub(last_save.elapsed())
+                    .filter(|remaining| !remaining.is_zero())
+            });
+            let frame = match remaining {
+                Some(remaining) => {
+                    thread::sleep(remaining);
+                    platform.capture_frame(self.output, false, self.region)?
+                }
+                None => frame,
+            };
+            last_save = Some(Instant::now());
+
+            let frame = self.post_process.apply(frame)?;
+            let path = format!(
+                "{}/{}-{}.{}",
+                self.directory,
+                self.filename,
+                unix_millis(),
+                self.encoding.extension()
+            );
+            let encode_start = Instant::now();
+            File::create(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|file| {
+                    write_to_file_with_options(file, self.encoding, frame, self.options.clone())
+                })
+                .with_context(|| write_failed(&path))?;
+            stats.record(
+                self.encoding,
+                fs::metadata(&path)?.len(),
+                encode_start.elapsed(),
+            );
+            info!("Saved change to {}", path);
+        }
+
+        info!("Stopped after {} screenshots", self.max_files);
+        Ok(())
+    }
+}
+
+/// Milliseconds since the UNIX epoch, used to name screenshots of changes
+fn unix_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis())
+        .unwrap_or_default()
+}
+
+/// Filename to use if none was given, named after the focused window if requested
+fn default_filename(args: &CmdArgs, platform: &dyn Platform) -> Result<String> {
+    if args.name_from_window {
+        let name = sanitize_filename(&platform.focused_window_name()?);
+        Ok(generate_filename(Some(&name)))
+    } else {
+        Ok(generate_filename(None))
+    }
+}
+
+/// Generate a filename from the current time and an optional label
+fn generate_filename(label: Option<&str>) -> String {
+    let time = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(n) => n.as_secs().to_string(),
+        Err(_) => {
+            warn!("SystemTime before UNIX EPOCH!");
+            "TIME-BEFORE-UNIX-EPOCH".into()
+        }
+    };
+    match label {
+        Some(label) => format!("screenshot-{}-{}", label, time),
+        None => format!("screenshot-{}", time),
+    }
+}
+
+/// Maximum number of characters of a window name used in a filename
+const MAX_WINDOW_NAME_LENGTH: usize = 64;
+
+/// Make a window name usable as part of a filename. Path separators, control characters and
+/// whitespace are replaced, leading dots removed and the length limited.
+pub fn sanitize_filename(name: &str) -> String {
+    let name = name
+        .chars()
+        .map(|c| {
+            if c == '/' || c == '\\' || c.is_control() || c.is_whitespace() {
+                '_'
+            } else {
+                c
+            }
+        })
+        .take(MAX_WINDOW_NAME_LENGTH)
+        .collect::<String>();
+    let name = name.trim_start_matches('.').trim_matches('_');
+    if name.is_empty() {
+        "window".into()
+    } else {
+        name.into()
+    }
+}
+
+/// Capture each of the comma separated outputs into its own file, named after the output.
+/// All names are checked before anything gets captured.
+fn run_for_outputs(
+    args: &CmdArgs,
+    config: &Config,
+    output_names: &str,
+    platform: &mut dyn Platform,
+) -> Result<()> {
+    let outputs = platform.outputs();
+    let output_names = output_names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect::<Vec<_>>();
+    let invalid_names = output_names
+        .iter()
+        .filter(|name| !outputs.iter().any(|output| output.name == **name))
+        .copied()
+        .collect::<Vec<_>>();
+    if !invalid_names.is_empty() {
+        bail!(ScrcapError::NoOutput(format!(
+            "No outputs named {} found!",
+            invalid_names.join(", ")
+        )));
+    }
+
+    let filename = match args.filename.clone() {
+        Some(filename) => filename,
+        None => default_filename(args, platform)?,
+    };
+    let (filename, image_encoding) = split_filename_extension(filename, args.encoding_format);
+    for output_name in output_names {
+        let mut output_args = args.clone();
+        output_args.output_name = Some(output_name.into());
+        output_args.outputs_where = None;
+        output_args.filename = Some(format!("{}-{}", filename, output_name));
+        output_args.encoding_format = Some(image_encoding);
+        // The arguments of the last capture were already filled in
+        output_args.repeat = false;
+        // The schedule was already waited for
+        output_args.at = None;
+        output_args.in_duration = None;
+        run(&output_args, config, platform)?;
+    }
+
+    Ok(())
+}
+
+/// Capture every region of the input, given as `X,Y WxH` per line, into its own file numbered
+/// by the line. Empty lines are skipped. The platform and its connection are reused for all
+/// captures.
+pub fn run_region_stream(
+    args: &CmdArgs,
+    config: &Config,
+    input: impl BufRead,
+    platform: &mut dyn Platform,
+) -> Result<()> {
+    let filename = match args.filename.clone() {
+        Some(filename) => filename,
+        None => default_filename(args, platform)?,
+    };
+    let (filename, image_encoding) = split_filename_extension(filename, args.encoding_format);
+
+    for (index, line) in input.lines().enumerate() {
+        let line = line.context("Could not read regions from stdin")?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line_number = index + 1;
+        let region: Region = line
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid region on line {}", line_number))?;
+
+        let mut region_args = args.clone();
+        region_args.region_stdin = false;
+        region_args.x = Some(region.x);
+        region_args.y = Some(region.y);
+        region_args.width = Some(region.width);
+        region_args.height = Some(region.height);
+        region_args.filename = Some(format!("{}-{}", filename, line_number));
+        region_args.encoding_format = Some(image_encoding);
+        // The arguments of the last capture were already filled in
+        region_args.repeat = false;
+        // The schedule was already waited for
+        region_args.at = None;
+        region_args.in_duration = None;
+        run(&region_args, config, platform)
+            .with_context(|| format!("Region on line {}", line_number))?;
+    }
+
+    Ok(())
+}
+
+/// Split a known extension off the filename and return the encoding format to use. The
+/// extension is kept if it does not match an explicitly given format.
+pub fn split_filename_extension(
+    filename: String,
+    encoding_format: Option<EncodingFormat>,
+) -> (String, EncodingFormat) {
+    let extension_format = filename
+        .rsplit_once('.')
+        .filter(|(stem, _)| !stem.is_empty())
+        .and_then(|(stem, extension)| {
+            EncodingFormat::from_extension(extension).map(|format| (stem.to_string(), format))
+        });
+
+    match (extension_format, encoding_format) {
+        (Some((stem, format)), None) => (stem, format),
+        (Some((stem, format)), Some(encoding_format))
+            if format.extension() == encoding_format.extension() =>
+        {
+            (stem, encoding_format)
+        }
+        (_, encoding_format) => (filename, encoding_format.unwrap_or(EncodingFormat::Png)),
+    }
+}
+
+/// Play the sound file in the background. Failing to play it is not an error.
+fn play_sound(path: &str) {
+    debug!("Play sound {}", path);
+    if let Err(err) = Command::new("paplay").arg(path).spawn() {
+        warn!("Could not play sound {}: {}", path, err);
+    }
+}
+
+/// Run the command with a shell after replacing `{}` with the quoted path
+fn run_command_on_file(command: &str, path: &str) -> Result<()> {
+    let quoted_path = format!("'{}'", path.replace('\'', "'\\''"));
+    let command = command.replace("{}", &quoted_path);
+    debug!("Run command: {}", command);
+
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(&command)
+        .status()
+        .with_context(|| format!("Could not run command: {}", command))?;
+    if !status.success() {
+        bail!("Command `{}` failed with {}", command, status);
+    }
+
+    Ok(())
+}
+
+/// Check if any region coordinates were given on the command line
+fn has_region_args(args: &CmdArgs) -> bool {
+    args.x.is_some()
+        || args.y.is_some()
+        || args.width.is_some()
+        || args.height.is_some()
+        || args.aspect.is_some()
+}
+
+/// Apply the aspect ratio argument to the region size, if given
+fn constrain_to_aspect(args: &CmdArgs, width: i32, height: i32) -> (i32, i32) {
+    match args.aspect {
+        Some(aspect) => {
+            aspect.constrain(width, height, args.width.is_some(), args.height.is_some())
+        }
+        None => (width, height),
+    }
+}
+
+/// Extract region from command line arguments
+pub fn get_region_from_args(args: &CmdArgs, output: &Output) -> Option<Result<Region>> {
+    if args.physical && has_region_args(args) {
+        let x = args.x.unwrap_or(0);
+        let y = args.y.unwrap_or(0);
+        let width = args
+            .width
+            .unwrap_or((output.width * output.scale - x).max(0));
+        let height = args
+            .height
+            .unwrap_or((output.height * output.scale - y).max(0));
+        let (width, height) = constrain_to_aspect(args, width, height);
+
+        if width <= 0 || height <= 0 {
+            return Some(Err(invalid_region("Capture region has zero area")));
+        }
+        let capture_region = output.logical_region(Region::new(x, y, width, height));
+        if !output.region().contains(capture_region) {
+            return Some(Err(invalid_region(format!(
+                "Region exceeds the {}x{} physical pixels of output {}",
+                output.width * output.scale,
+                output.height * output.scale,
+                output.name
+            ))));
+        }
+
+        return Some(Ok(capture_region));
+    }
+
+    if has_region_args(args) {
+        let x = args.x.unwrap_or(0);
+        let y = args.y.unwrap_or(0);
+        let width = args.width.unwrap_or((output.width as i32 - x).max(0));
+        let height = args.height.unwrap_or((output.height as i32 - y).max(0));
+        let (width, height) = constrain_to_aspect(args, width, height);
+
+        let capture_region = Region::new(x, y, width, height);
+        if capture_region.area() == 0 {
+            return Some(Err(invalid_region("Capture region has zero area")));
+        }
+        // Without an explicit output the region may span multiple outputs
+        if args.output_name.is_some() && !output.region().contains(capture_region) {
+            return Some(Err(invalid_region("Region is invalid")));
+        }
+
+        return Some(Ok(Region::new(x, y, width, height)));
+    }
+
+    None
+}
+
+/// Extract region from command line arguments, interpreted as offset inside the window of
+/// --active or --wait-for-window
+pub fn get_window_region_from_args(args: &CmdArgs, window: Region) -> Option<Result<Region>> {
+    if has_region_args(args) {
+        let x = args.x.unwrap_or(0);
+        let y = args.y.unwrap_or(0);
+        let width = args.width.unwrap_or((window.width - x).max(0));
+        let height = args.height.unwrap_or((window.height - y).max(0));
+        let (width, height) = constrain_to_aspect(args, width, height);
+
+        let capture_region = Region::new(window.x + x, window.y + y, width, height);
+        if capture_region.area() == 0 {
+            return Some(Err(invalid_region("Capture region has zero area")));
+        }
+        if !window.contains(capture_region) {
+            return Some(Err(invalid_region(
+                "Region exceeds the bounds of the window",
+            )));
+        }
+
+        return Some(Ok(capture_region));
+    }
+
+    None
+}
+
+/// Path of the FIFO the filename names, either absolute or relative to the directory
+pub fn find_fifo(filename: &str, directory: &str) -> Option<String> {
+    let path = if Path::new(filename).is_absolute() {
+        filename.to_string()
+    } else {
+        format!("{}/{}", directory, filename)
+    };
+    let is_fifo = fs::metadata(&path)
+        .map(|metadata| metadata.file_type().is_fifo())
+        .unwrap_or(false);
+    is_fifo.then_some(path)
+}
+
+fn write_failed(path: &str) -> ScrcapError {
+    ScrcapError::WriteFailed(format!("Could not write {}", path))
+}
+
+fn invalid_region(message: impl Into<String>) -> anyhow::Error {
+    ScrcapError::InvalidRegion(message.into()).into()
+}
+
+/// Output to capture if none was given: the focused one, or the first one if the platform does
+/// not know which output has the focus
+pub fn default_output<'a>(platform: &dyn Platform, outputs: &'a [Output]) -> Result<&'a Output> {
+    match platform.focused_output() {
+        Ok(focused) => {
+            if let Some(output) = outputs.iter().find(|output| output.name == focused.name) {
+                return Ok(output);
+            }
+        }
+        Err(err) => debug!("Focused output is unknown, taking the first one: {:#}", err),
+    }
+    get_output(None, outputs)
+}
+
+/// Find the matching output to output_name or return the first output
+pub fn get_output(output_name: Option<String>, outputs: &[Output]) -> Result<&Output> {
+    if let Some(output_name) = output_name {
+        // Find output with matching name
+        for output in outputs {
+            if output.name == output_name {
+                return Ok(output);
+            }
+        }
+        bail!(ScrcapError::NoOutput(format!(
+            "No output named {} found!",
+            output_name
+        )));
+    } else if !outputs.is_empty() {
+        // Take the first one
+        return Ok(&outputs[0]);
+    } else {
+        bail!(ScrcapError::NoOutput(NO_OUTPUTS_ERROR.into()));
+    };
+}
+
+/// Write the presets of the config with the regions they resolve to on the output as aligned
+/// columns
+pub fn write_presets(config: &Config, output: &Output, mut report: impl Write) -> Result<()> {
+    if config.presets.is_empty() {
+        writeln!(report, "No presets configured")?;
+        return Ok(());
+    }
+
+    let mut names = config.presets.keys().collect::<Vec<_>>();
+    names.sort();
+    let rows = names
+        .into_iter()
+        .map(|name| {
+            let region = match config.preset(name, output) {
+                Ok(region) => region.to_string(),
+                Err(err) => format!("invalid: {:#}", err),
+            };
+            [
+                name.clone(),
+                config.presets[name].clone(),
+                output.name.clone(),
+                region,
+            ]
+        })
+        .collect::<Vec<_>>();
+
+    let header = ["NAME", "GEOMETRY", "OUTPUT", "REGION"].map(String::from);
+    let mut widths = header.clone().map(|column| column.len());
+    for row in &rows {
+        for (width, column) in widths.iter_mut().zip(row) {
+            *width = (*width).max(column.len());
+        }
+    }
+    for row in std::iter::once(&header).chain(&rows) {
+        writeln!(
+            report,
+            "{:<w0$}  {:<w1$}  {:<w2$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2]
+        )?;
+    }
+
+    Ok(())
+}
+
+/// List the outputs and let the user choose one by its number. Invalid input gets asked again.
+pub fn pick_output(
+    outputs: &[Output],
+    mut input: impl BufRead,
+    mut prompt: impl Write,
+) -> Result<&Output> {
+    match outputs {
+        [] => bail!(ScrcapError::NoOutput(NO_OUTPUTS_ERROR.into())),
+        [output] => return Ok(output),
+        _ => (),
+    }
+
+    for (index, output) in outputs.iter().enumerate() {
+        writeln!(
+            prompt,
+            "{}: {} ({}x{} at {},{})",
+            index + 1,
+            output.name,
+            output.width,
+            output.height,
+            output.x,
+            output.y
+        )?;
+    }
+
+    loop {
+        write!(prompt, "Output [1-{}]: ", outputs.len())?;
+        prompt.flush()?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            bail!("No output selected");
+        }
+        match line.trim().parse::<usize>() {
+            Ok(number) if (1..=outputs.len()).contains(&number) => {
+                return Ok(&outputs[number - 1]);
+            }
+            _ => writeln!(prompt, "Invalid choice: {}", line.trim())?,
+        }
+    }
+}
+
+/// Find the output that contains the whole region
+pub fn find_output_from_region(region: Region, outputs: &[Output]) -> Result<&Output> {
+    for output in outputs {
+        if output.region().contains(region) {
+            return Ok(output);
+        }
+    }
+    Err(region_placement_error(region, outputs))
+}
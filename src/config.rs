@@ -0,0 +1,124 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::error::ScrcapError;
+use crate::output::PngCompression;
+use crate::platform::{Output, Region};
+use anyhow::{anyhow, bail, Context, Result};
+use log::debug;
+use serde::Deserialize;
+
+/// Settings read from `$XDG_CONFIG_HOME/scrcap/config.toml`
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Named regions given as `X,Y WxH`, relative to the captured output
+    pub presets: HashMap<String, String>,
+    /// Settings of the outputs by their name
+    pub outputs: HashMap<String, OutputConfig>,
+    /// Default encoder settings, overridden by the command line
+    pub encoding: EncodingConfig,
+}
+
+/// Default encoder settings
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct EncodingConfig {
+    /// Quality of JPEG images from 1 to 100
+    pub jpeg_quality: Option<u8>,
+    /// Compression level of PNG images, one of fast, default and best
+    pub png_compression: Option<PngCompression>,
+}
+
+/// Settings of a single output
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct OutputConfig {
+    /// Region given as `X,Y WxH` that gets captured if no region is given explicitly, e.g. to
+    /// leave out a panel
+    pub region: Option<String>,
+}
+
+impl Config {
+    /// Location of the config file
+    pub fn path() -> Result<PathBuf> {
+        let Some(config_dir) = dirs::config_dir() else {
+            bail!("Could not find the config directory");
+        };
+        Ok(config_dir.join("scrcap").join("config.toml"))
+    }
+
+    /// Load the config file. A missing file results in the default config.
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        match fs::read_to_string(&path) {
+            Ok(data) => Self::parse(&data).with_context(|| format!("Invalid config {:?}", path)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                debug!("No config found at {:?}", path);
+                Ok(Self::default())
+            }
+            Err(err) => Err(err).with_context(|| format!("Could not read config {:?}", path)),
+        }
+    }
+
+    pub fn parse(data: &str) -> Result<Self> {
+        Ok(toml::from_str(data)?)
+    }
+
+    /// Resolve the preset with the given name to a global region on the output
+    pub fn preset(&self, name: &str, output: &Output) -> Result<Region> {
+        let geometry = self
+            .presets
+            .get(name)
+            .ok_or_else(|| anyhow!("No preset named {} found", name))?;
+        resolve_geometry(&format!("Preset {}", name), geometry, output)
+    }
+
+    /// Resolve the default region of the output to a global region, if it has one
+    pub fn default_region(&self, output: &Output) -> Result<Option<Region>> {
+        let Some(geometry) = self
+            .outputs
+            .get(&output.name)
+            .and_then(|output_config| output_config.region.as_ref())
+        else {
+            return Ok(None);
+        };
+        let region = resolve_geometry(
+            &format!("Default region of output {}", output.name),
+            geometry,
+            output,
+        )?;
+        Ok(Some(region))
+    }
+}
+
+/// Parse a geometry relative to the output and move it into global coordinates. The
+/// description names the geometry in errors.
+fn resolve_geometry(description: &str, geometry: &str, output: &Output) -> Result<Region> {
+    let region: Region = geometry
+        .parse()
+        .with_context(|| format!("{} has an invalid geometry", description))?;
+
+    let region = Region::new(
+        output.x + region.x,
+        output.y + region.y,
+        region.width,
+        region.height,
+    );
+    if region.area() == 0 {
+        bail!(ScrcapError::InvalidRegion(format!(
+            "{} has zero area",
+            description
+        )));
+    }
+    if !output.region().contains(region) {
+        bail!(ScrcapError::InvalidRegion(format!(
+            "{} exceeds the bounds of output {}",
+            description, output.name
+        )));
+    }
+
+    Ok(region)
+}
@@ -0,0 +1,43 @@
+use thiserror::Error;
+
+/// Classes of failures with their own exit code, so that scripts can react to the reason. The
+/// errors are either returned directly or attached as context to the underlying error.
+#[derive(Error, Debug)]
+pub enum ScrcapError {
+    #[error("{0}")]
+    NoOutput(String),
+    #[error("{0}")]
+    InvalidRegion(String),
+    #[error("{0}")]
+    MissingProtocol(String),
+    #[error("{0}")]
+    CaptureFailed(String),
+    #[error("{0}")]
+    WriteFailed(String),
+    #[error("{0}")]
+    DiffExceeded(String),
+}
+
+/// Exit code of any other failure
+pub const EXIT_FAILURE: u8 = 1;
+
+impl ScrcapError {
+    /// Exit code for the class of the failure. 2 is left for invalid arguments, which clap
+    /// reports with it.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            ScrcapError::NoOutput(_) => 3,
+            ScrcapError::InvalidRegion(_) => 4,
+            ScrcapError::MissingProtocol(_) => 5,
+            ScrcapError::CaptureFailed(_) => 6,
+            ScrcapError::WriteFailed(_) => 7,
+            ScrcapError::DiffExceeded(_) => 8,
+        }
+    }
+}
+
+/// Exit code for the error, found in the error itself or its context
+pub fn exit_code(err: &anyhow::Error) -> u8 {
+    err.downcast_ref::<ScrcapError>()
+        .map_or(EXIT_FAILURE, ScrcapError::exit_code)
+}
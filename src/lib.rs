@@ -0,0 +1,5 @@
+pub mod cli;
+pub mod config;
+pub mod error;
+pub mod output;
+pub mod platform;
@@ -1,22 +1,38 @@
-use crate::platform::Frame;
-use anyhow::Result;
+use crate::platform::{
+    image_content_region, transform_image, Frame, FrameFormat, OutputTransform, Timings,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
 use image::codecs::pnm::{self, PnmEncoder};
-use image::ImageEncoder;
+use image::imageops::{self, FilterType};
 use image::{
-    codecs::{jpeg::JpegEncoder, png::PngEncoder},
+    codecs::{
+        jpeg::JpegEncoder,
+        png::{CompressionType, FilterType as PngFilterType, PngEncoder},
+    },
     ColorType,
 };
-use log::debug;
+use image::{DynamicImage, ImageBuffer, ImageEncoder, Pixel, Rgba, RgbaImage};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize, Serializer};
 use std::env;
+use std::fs;
 use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
 
 /// Supported image encoding formats.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "String", from = "String")]
 pub enum EncodingFormat {
     /// Jpeg / Jpg encoder.
     Jpg,
     /// Png encoder.
     Png,
+    /// Png encoder with 16 bits per channel, keeps the precision of 10-bit frames.
+    Png16,
     /// Ppm encoder
     Ppm,
 }
@@ -28,6 +44,7 @@ impl From<String> for EncodingFormat {
             "jpg" => EncodingFormat::Jpg,
             "jpeg" => EncodingFormat::Jpg,
             "png" => EncodingFormat::Png,
+            "png16" => EncodingFormat::Png16,
             "ppm" => EncodingFormat::Ppm,
             _ => EncodingFormat::Png,
         }
@@ -38,74 +55,740 @@ impl Into<String> for EncodingFormat {
     fn into(self) -> String {
         match self {
             EncodingFormat::Png => "png".into(),
+            EncodingFormat::Png16 => "png16".into(),
             EncodingFormat::Jpg => "jpg".into(),
             EncodingFormat::Ppm => "ppm".into(),
         }
     }
 }
 
+impl EncodingFormat {
+    /// Format for a known file extension.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_lowercase().as_str() {
+            "jpg" | "jpeg" | "png" | "ppm" => Some(Self::from(extension.to_string())),
+            _ => None,
+        }
+    }
+
+    /// File extension of images encoded with this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            EncodingFormat::Png | EncodingFormat::Png16 => "png",
+            EncodingFormat::Jpg => "jpg",
+            EncodingFormat::Ppm => "ppm",
+        }
+    }
+
+    /// Mime type of images encoded with this format.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            EncodingFormat::Png | EncodingFormat::Png16 => "image/png",
+            EncodingFormat::Jpg => "image/jpeg",
+            EncodingFormat::Ppm => "image/x-portable-pixmap",
+        }
+    }
+}
+
+/// Color profile to tag images with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColorProfile {
+    /// The sRGB color space, tagged without embedding a profile.
+    Srgb,
+    /// An ICC profile file that gets embedded.
+    Icc(PathBuf),
+}
+
+impl FromStr for ColorProfile {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        if value.eq_ignore_ascii_case("srgb") {
+            return Ok(ColorProfile::Srgb);
+        }
+        Ok(ColorProfile::Icc(value.into()))
+    }
+}
+
+/// Settings of the encoders that are not part of the encoding format.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EncodingOptions {
+    /// Encode JPEG images progressively. Needs the progressive-jpeg feature.
+    pub progressive: bool,
+    /// Color profile to tag PNG images with.
+    pub color_profile: Option<ColorProfile>,
+    /// Quality of JPEG images from 1 to 100. Defaults to `DEFAULT_JPEG_QUALITY`.
+    pub jpeg_quality: Option<u8>,
+    /// Compression level of PNG images.
+    pub png_compression: Option<PngCompression>,
+    /// Pixel density horizontally and vertically in pixels per meter, stored in PNG images.
+    pub pixels_per_meter: Option<(u32, u32)>,
+    /// Text entries as keyword and text, stored in PNG images.
+    pub metadata: Vec<(String, String)>,
+    /// Write PPM images as ASCII (P3) instead of binary (P6).
+    pub ppm_ascii: bool,
+}
+
+/// Quality of JPEG images if none is given, the same as the default of the image crate.
+pub const DEFAULT_JPEG_QUALITY: u8 = 75;
+
+/// Compression level of PNG images, trading encoding time for file size.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PngCompression {
+    Fast,
+    #[default]
+    Default,
+    Best,
+}
+
+impl FromStr for PngCompression {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "fast" => Ok(PngCompression::Fast),
+            "default" => Ok(PngCompression::Default),
+            "best" => Ok(PngCompression::Best),
+            _ => bail!("PNG compression must be fast, default or best"),
+        }
+    }
+}
+
+impl From<PngCompression> for CompressionType {
+    fn from(value: PngCompression) -> Self {
+        match value {
+            PngCompression::Fast => CompressionType::Fast,
+            PngCompression::Default => CompressionType::Default,
+            PngCompression::Best => CompressionType::Best,
+        }
+    }
+}
+
 // Write an instance of FrameCopy to anything that implements Write trait. Eg: Stdout or a file
 /// on the disk.
 pub fn write_to_file(
-    mut output_file: impl Write,
+    output_file: impl Write,
     encoding_format: EncodingFormat,
     frame_copy: Frame,
+) -> Result<()> {
+    write_to_file_with_options(
+        output_file,
+        encoding_format,
+        frame_copy,
+        EncodingOptions::default(),
+    )
+}
+
+/// Like `write_to_file`, but with non default encoder settings.
+pub fn write_to_file_with_options(
+    output_file: impl Write,
+    encoding_format: EncodingFormat,
+    frame_copy: Frame,
+    options: EncodingOptions,
+) -> Result<()> {
+    write_buffer_to_file(
+        output_file,
+        &frame_copy.packed_data()?,
+        frame_copy.frame_format.width,
+        frame_copy.frame_format.height,
+        frame_copy.frame_color_type,
+        encoding_format,
+        options,
+    )
+}
+
+/// Encode pixel data without row padding, e.g. of an image that was processed after capturing,
+/// to anything that implements Write.
+pub fn write_buffer_to_file(
+    mut output_file: impl Write,
+    data: &[u8],
+    width: u32,
+    height: u32,
+    color_type: ColorType,
+    encoding_format: EncodingFormat,
+    options: EncodingOptions,
 ) -> Result<()> {
     debug!(
         "Writing to disk with encoding format: {:?}",
         encoding_format
     );
+    if options.color_profile.is_some()
+        && !matches!(encoding_format, EncodingFormat::Png | EncodingFormat::Png16)
+    {
+        warn!("Color profiles are only supported for PNG images");
+    }
+    if !options.metadata.is_empty()
+        && !matches!(encoding_format, EncodingFormat::Png | EncodingFormat::Png16)
+    {
+        warn!("Metadata is only supported for PNG images");
+    }
+
+    let jpeg_quality = options.jpeg_quality.unwrap_or(DEFAULT_JPEG_QUALITY);
+    if !(1..=100).contains(&jpeg_quality) {
+        bail!("JPEG quality must be between 1 and 100");
+    }
+
     match encoding_format {
-        EncodingFormat::Jpg => {
-            JpegEncoder::new(&mut output_file).write_image(
-                &frame_copy.frame_mmap,
-                frame_copy.frame_format.width,
-                frame_copy.frame_format.height,
-                frame_copy.frame_color_type,
+        EncodingFormat::Jpg if options.progressive => {
+            write_progressive_jpeg(
+                &mut output_file,
+                data,
+                width,
+                height,
+                color_type,
+                jpeg_quality,
             )?;
-            output_file.flush()?;
         }
-        EncodingFormat::Png => {
-            PngEncoder::new(&mut output_file).write_image(
-                &frame_copy.frame_mmap,
-                frame_copy.frame_format.width,
-                frame_copy.frame_format.height,
-                frame_copy.frame_color_type,
-            )?;
+        EncodingFormat::Jpg => {
+            JpegEncoder::new_with_quality(&mut output_file, jpeg_quality)
+                .write_image(data, width, height, color_type)?;
             output_file.flush()?;
         }
-        EncodingFormat::Ppm => {
-            let rgb8_data = if let ColorType::Rgba8 = frame_copy.frame_color_type {
-                let mut data = Vec::with_capacity(
-                    (3 * frame_copy.frame_format.width * frame_copy.frame_format.height) as _,
-                );
-                for chunk in frame_copy.frame_mmap.chunks_exact(4) {
-                    data.extend_from_slice(&chunk[..3]);
-                }
-                data
+        EncodingFormat::Png | EncodingFormat::Png16 => {
+            if options.color_profile.is_some()
+                || options.pixels_per_meter.is_some()
+                || !options.metadata.is_empty()
+            {
+                let mut png = Vec::new();
+                png_encoder(&mut png, options.png_compression)
+                    .write_image(data, width, height, color_type)?;
+                tag_png(&mut png, &options)?;
+                output_file.write_all(&png)?;
             } else {
-                unimplemented!("Currently only ColorType::Rgba8 is supported")
-            };
-
-            PnmEncoder::new(&mut output_file)
-                .with_subtype(pnm::PnmSubtype::Pixmap(pnm::SampleEncoding::Binary))
-                .write_image(
-                    &rgb8_data,
-                    frame_copy.frame_format.width,
-                    frame_copy.frame_format.height,
-                    ColorType::Rgb8,
-                )?;
+                png_encoder(&mut output_file, options.png_compression)
+                    .write_image(data, width, height, color_type)?;
+            }
             output_file.flush()?;
         }
+        EncodingFormat::Ppm => write_ppm(
+            &mut output_file,
+            data,
+            width,
+            height,
+            color_type,
+            options.ppm_ascii,
+        )?,
+    }
+
+    Ok(())
+}
+
+/// Create a PNG encoder with the given or the default compression.
+fn png_encoder<W: Write>(output_file: W, compression: Option<PngCompression>) -> PngEncoder<W> {
+    PngEncoder::new_with_quality(
+        output_file,
+        compression.unwrap_or_default().into(),
+        PngFilterType::Adaptive,
+    )
+}
+
+/// Length of the PNG signature and the IHDR chunk, which must come first.
+const PNG_HEADER_LENGTH: usize = 8 + 4 + 4 + 13 + 4;
+
+/// Add the metadata of the options to an encoded PNG image: tEXt chunks for the text entries, a
+/// pHYs chunk for the pixel density and a sRGB or iCCP chunk for the color profile.
+fn tag_png(png: &mut Vec<u8>, options: &EncodingOptions) -> Result<()> {
+    // Inserting in reverse keeps the order of the entries
+    for (keyword, text) in options.metadata.iter().rev() {
+        if keyword.is_empty() || keyword.len() > 79 {
+            bail!("PNG metadata keyword must have 1 to 79 characters");
+        }
+        let mut data = latin1(keyword);
+        data.push(0);
+        data.extend(latin1(text));
+        insert_png_chunk(png, b"tEXt", &data)?;
+    }
+
+    if let Some((x, y)) = options.pixels_per_meter {
+        let mut data = Vec::with_capacity(9);
+        data.extend_from_slice(&x.to_be_bytes());
+        data.extend_from_slice(&y.to_be_bytes());
+        // The unit is the meter
+        data.push(1);
+        insert_png_chunk(png, b"pHYs", &data)?;
+    }
+
+    let Some(color_profile) = &options.color_profile else {
+        return Ok(());
+    };
+    match color_profile {
+        // Perceptual rendering intent
+        ColorProfile::Srgb => insert_png_chunk(png, b"sRGB", &[0]),
+        ColorProfile::Icc(path) => {
+            let profile =
+                fs::read(path).with_context(|| format!("Could not read ICC profile {:?}", path))?;
+            // Keyword, null separator and compression method deflate
+            let mut data = b"ICC profile\0\0".to_vec();
+            let mut encoder = ZlibEncoder::new(&mut data, Compression::default());
+            encoder.write_all(&profile)?;
+            encoder.finish()?;
+            insert_png_chunk(png, b"iCCP", &data)
+        }
+    }
+}
+
+/// Encode text as Latin-1, which tEXt chunks are limited to. Other characters become `?`.
+fn latin1(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|character| u8::try_from(character).unwrap_or(b'?'))
+        .collect()
+}
+
+/// Insert a chunk right after the IHDR chunk of an encoded PNG image.
+fn insert_png_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) -> Result<()> {
+    if png.len() < PNG_HEADER_LENGTH || &png[12..16] != b"IHDR" {
+        bail!("PNG image does not start with an IHDR chunk");
+    }
+
+    let mut chunk = Vec::with_capacity(data.len() + 12);
+    chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(chunk_type);
+    chunk.extend_from_slice(data);
+    let crc = crc32fast::hash(&chunk[4..]);
+    chunk.extend_from_slice(&crc.to_be_bytes());
+
+    png.splice(PNG_HEADER_LENGTH..PNG_HEADER_LENGTH, chunk);
+    Ok(())
+}
+
+/// Write the pixel data as progressive JPEG. Only Rgba8 data is supported.
+#[cfg(feature = "progressive-jpeg")]
+fn write_progressive_jpeg(
+    mut output_file: impl Write,
+    data: &[u8],
+    width: u32,
+    height: u32,
+    color_type: ColorType,
+    quality: u8,
+) -> Result<()> {
+    if color_type != ColorType::Rgba8 {
+        bail!("Can not encode {:?} image as progressive JPEG", color_type);
+    }
+    let (Ok(width), Ok(height)) = (u16::try_from(width), u16::try_from(height)) else {
+        bail!(
+            "JPEG images can be at most {} pixels wide and high",
+            u16::MAX
+        );
+    };
+
+    let mut encoder = jpeg_encoder::Encoder::new(&mut output_file, quality);
+    encoder.set_progressive(true);
+    encoder.encode(data, width, height, jpeg_encoder::ColorType::Rgba)?;
+    output_file.flush()?;
+
+    Ok(())
+}
+
+#[cfg(not(feature = "progressive-jpeg"))]
+fn write_progressive_jpeg(
+    _output_file: impl Write,
+    _data: &[u8],
+    _width: u32,
+    _height: u32,
+    _color_type: ColorType,
+    _quality: u8,
+) -> Result<()> {
+    bail!("Progressive JPEG encoding needs scrcap to be built with the progressive-jpeg feature")
+}
+
+/// Write the pixel data as binary or ASCII PPM. The alpha channel gets dropped and 16-bit data
+/// keeps its depth.
+fn write_ppm(
+    mut output_file: impl Write,
+    data: &[u8],
+    width: u32,
+    height: u32,
+    color_type: ColorType,
+    ascii: bool,
+) -> Result<()> {
+    let (sample_bytes, channels) = match color_type {
+        ColorType::Rgb8 => (1, 3),
+        ColorType::Rgba8 => (1, 4),
+        ColorType::Rgb16 => (2, 3),
+        ColorType::Rgba16 => (2, 4),
+        color_type => bail!("Can not encode {:?} image as PPM", color_type),
+    };
+    let pixels = width as usize * height as usize;
+    if data.len() < pixels * sample_bytes * channels {
+        bail!("Image data is smaller than {}x{} pixels", width, height);
+    }
+
+    let mut rgb_data = Vec::with_capacity(pixels * 3 * sample_bytes);
+    for pixel in data.chunks_exact(sample_bytes * channels).take(pixels) {
+        rgb_data.extend_from_slice(&pixel[..3 * sample_bytes]);
+    }
+
+    if sample_bytes == 1 {
+        let sample_encoding = if ascii {
+            pnm::SampleEncoding::Ascii
+        } else {
+            pnm::SampleEncoding::Binary
+        };
+        PnmEncoder::new(&mut output_file)
+            .with_subtype(pnm::PnmSubtype::Pixmap(sample_encoding))
+            .write_image(&rgb_data, width, height, ColorType::Rgb8)?;
+    } else if ascii {
+        // One line of samples per row
+        write!(output_file, "P3\n{} {}\n65535\n", width, height)?;
+        for row in rgb_data.chunks_exact(width.max(1) as usize * 6) {
+            let samples: Vec<String> = row
+                .chunks_exact(2)
+                .map(|sample| u16::from_ne_bytes([sample[0], sample[1]]).to_string())
+                .collect();
+            writeln!(output_file, "{}", samples.join(" "))?;
+        }
+    } else {
+        // The PNM encoder only accepts Rgb8 pixmaps, so write the 16-bit one by hand. Samples
+        // are stored in native byte order, but PPM wants them big endian.
+        write!(output_file, "P6\n{} {}\n65535\n", width, height)?;
+        for sample in rgb_data.chunks_exact(2) {
+            output_file.write_all(&u16::from_ne_bytes([sample[0], sample[1]]).to_be_bytes())?;
+        }
     }
+    output_file.flush()?;
+
+    Ok(())
+}
 
+/// Write the unconverted pixel data of a frame, including any row padding.
+pub fn write_raw(mut output_file: impl Write, frame: &Frame) -> Result<()> {
+    output_file.write_all(&frame.frame_mmap)?;
+    output_file.flush()?;
     Ok(())
 }
 
+/// Write a JSON description of the pixel layout written by `write_raw`.
+pub fn write_raw_description(mut output_file: impl Write, frame: &Frame) -> Result<()> {
+    serde_json::to_writer(&mut output_file, &frame.frame_format)?;
+    writeln!(output_file)?;
+    output_file.flush()?;
+    Ok(())
+}
+
+/// Summary of a saved screenshot, printed by --json
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CaptureReport {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+    pub format: FrameFormat,
+    pub bytes: u64,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_timings"
+    )]
+    pub timings: Option<Timings>,
+}
+
+/// Timings in milliseconds, as written by `write_capture_json`
+#[derive(Serialize)]
+struct TimingsMilliseconds {
+    connect: f64,
+    discover_outputs: f64,
+    capture: f64,
+    convert: f64,
+    encode: f64,
+}
+
+fn serialize_timings<S: Serializer>(
+    timings: &Option<Timings>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let milliseconds = |duration: Duration| duration.as_nanos() as f64 / 1_000_000.0;
+    timings
+        .map(|timings| TimingsMilliseconds {
+            connect: milliseconds(timings.connect),
+            discover_outputs: milliseconds(timings.discover_outputs),
+            capture: milliseconds(timings.capture),
+            convert: milliseconds(timings.convert),
+            encode: milliseconds(timings.encode),
+        })
+        .serialize(serializer)
+}
+
+/// Write the report as one line of JSON. Timings are given in milliseconds.
+pub fn write_capture_json(mut output_file: impl Write, report: &CaptureReport) -> Result<()> {
+    serde_json::to_writer(&mut output_file, report)?;
+    writeln!(output_file)?;
+    output_file.flush()?;
+    Ok(())
+}
+
+/// Distance of the watermark to the edges of the screenshot in pixels.
+pub const WATERMARK_MARGIN: i64 = 16;
+
+/// Corner of the screenshot the watermark is placed in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WatermarkPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl FromStr for WatermarkPosition {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "top-left" => Ok(WatermarkPosition::TopLeft),
+            "top-right" => Ok(WatermarkPosition::TopRight),
+            "bottom-left" => Ok(WatermarkPosition::BottomLeft),
+            "bottom-right" => Ok(WatermarkPosition::BottomRight),
+            _ => bail!("Unknown watermark position {}", value),
+        }
+    }
+}
+
+/// Image to put onto the screenshot, given as `PATH[:POSITION]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watermark {
+    pub path: PathBuf,
+    pub position: WatermarkPosition,
+}
+
+impl FromStr for Watermark {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        // Paths may contain colons too, so only a known position is split off
+        if let Some((path, position)) = value.rsplit_once(':') {
+            if let Ok(position) = position.parse() {
+                return Ok(Self {
+                    path: path.into(),
+                    position,
+                });
+            }
+        }
+
+        Ok(Self {
+            path: value.into(),
+            position: WatermarkPosition::BottomRight,
+        })
+    }
+}
+
+/// Blend the watermark onto the image at the given corner
+pub fn overlay_watermark<P: Pixel>(
+    image: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    watermark: &ImageBuffer<P, Vec<P::Subpixel>>,
+    position: WatermarkPosition,
+) {
+    let right = image.width() as i64 - watermark.width() as i64 - WATERMARK_MARGIN;
+    let bottom = image.height() as i64 - watermark.height() as i64 - WATERMARK_MARGIN;
+    let (x, y) = match position {
+        WatermarkPosition::TopLeft => (WATERMARK_MARGIN, WATERMARK_MARGIN),
+        WatermarkPosition::TopRight => (right, WATERMARK_MARGIN),
+        WatermarkPosition::BottomLeft => (WATERMARK_MARGIN, bottom),
+        WatermarkPosition::BottomRight => (right, bottom),
+    };
+    imageops::overlay(image, watermark, x, y);
+}
+
+/// Processing of a screenshot after capturing. All steps work on one image that gets made from
+/// the frame once, in this order:
+///
+/// 1. `transform` corrects the orientation, so the later steps see the screenshot upright
+/// 2. `crop_to_content` cuts off uniform borders, keeping the frame if it has only one color
+/// 3. `watermark` gets placed into a corner of the final image
+#[derive(Debug, Default, Clone)]
+pub struct PostProcess {
+    pub transform: Option<OutputTransform>,
+    /// Tolerance of the border color, see `image_content_region`
+    pub crop_to_content: Option<u8>,
+    pub watermark: Option<(RgbaImage, WatermarkPosition)>,
+}
+
+impl PostProcess {
+    pub fn is_empty(&self) -> bool {
+        self.transform.is_none() && self.crop_to_content.is_none() && self.watermark.is_none()
+    }
+
+    /// Apply all steps to the image in the order of the pipeline. Rgba16 images keep their
+    /// depth, all other images get converted to Rgba8.
+    pub fn apply_to_image(&self, image: DynamicImage) -> DynamicImage {
+        let image = match image {
+            DynamicImage::ImageRgba16(image) => DynamicImage::ImageRgba16(self.transformed(image)),
+            image => DynamicImage::ImageRgba8(self.transformed(image.into_rgba8())),
+        };
+
+        // The tolerance is given for 8 bits per channel
+        let content_region = |tolerance| match image.as_rgba8() {
+            Some(rgba) => image_content_region(rgba, tolerance),
+            None => image_content_region(&image.to_rgba8(), tolerance),
+        };
+        let mut image = match self.crop_to_content.map(content_region) {
+            Some(Some(content)) => {
+                debug!("Crop screenshot to content {:?}", content);
+                image.crop_imm(
+                    content.x as u32,
+                    content.y as u32,
+                    content.width as u32,
+                    content.height as u32,
+                )
+            }
+            Some(None) => {
+                warn!("Screenshot has only one color, not cropping it");
+                image
+            }
+            None => image,
+        };
+
+        if let Some((watermark, position)) = &self.watermark {
+            if let Some(image) = image.as_mut_rgba16() {
+                let watermark = DynamicImage::ImageRgba8(watermark.clone()).into_rgba16();
+                overlay_watermark(image, &watermark, *position);
+            } else if let Some(image) = image.as_mut_rgba8() {
+                overlay_watermark(image, watermark, *position);
+            }
+        }
+        image
+    }
+
+    fn transformed<P: Pixel + 'static>(
+        &self,
+        image: ImageBuffer<P, Vec<P::Subpixel>>,
+    ) -> ImageBuffer<P, Vec<P::Subpixel>> {
+        match self.transform {
+            Some(transform) => transform_image(image, transform),
+            None => image,
+        }
+    }
+
+    /// Apply all steps to the frame. The frame is returned as it is if there is nothing to do,
+    /// otherwise only Rgba8 and Rgba16 frames are supported.
+    pub fn apply(&self, frame: Frame) -> Result<Frame> {
+        if self.is_empty() {
+            return Ok(frame);
+        }
+        let format = frame.frame_format.format;
+        if frame.frame_color_type == ColorType::Rgba16 {
+            let image = DynamicImage::ImageRgba16(frame.to_rgba16_image()?);
+            return Frame::from_rgba16_image(format, &self.apply_to_image(image).into_rgba16());
+        }
+        let image = DynamicImage::ImageRgba8(frame.to_rgba_image()?);
+        Frame::from_rgba_image(format, &self.apply_to_image(image).into_rgba8())
+    }
+}
+
+/// Load the image of a watermark.
+pub fn load_watermark(watermark: &Watermark) -> Result<RgbaImage> {
+    Ok(image::open(&watermark.path)
+        .map_err(|err| anyhow!("Could not load watermark {:?}: {}", watermark.path, err))?
+        .to_rgba8())
+}
+
+/// Load the baseline image of --diff.
+pub fn load_baseline(path: &Path) -> Result<RgbaImage> {
+    Ok(image::open(path)
+        .map_err(|err| anyhow!("Could not load baseline {:?}: {}", path, err))?
+        .to_rgba8())
+}
+
+/// Result of comparing a screenshot pixel by pixel with a baseline.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageDiff {
+    pub differing_pixels: u64,
+    pub total_pixels: u64,
+    /// The screenshot dimmed to grey, with the differing pixels in red.
+    pub diff_image: RgbaImage,
+}
+
+impl ImageDiff {
+    /// Share of the differing pixels in percent.
+    pub fn percentage(&self) -> f64 {
+        if self.total_pixels == 0 {
+            return 0.0;
+        }
+        self.differing_pixels as f64 * 100.0 / self.total_pixels as f64
+    }
+}
+
+/// Compare the image with the baseline, which must have the same size.
+pub fn diff_images(image: &RgbaImage, baseline: &RgbaImage) -> Result<ImageDiff> {
+    if image.dimensions() != baseline.dimensions() {
+        bail!(
+            "Screenshot is {}x{} pixels, but the baseline is {}x{}",
+            image.width(),
+            image.height(),
+            baseline.width(),
+            baseline.height()
+        );
+    }
+
+    let mut differing_pixels = 0;
+    let mut diff_image = RgbaImage::new(image.width(), image.height());
+    for ((pixel, baseline_pixel), diff_pixel) in image
+        .pixels()
+        .zip(baseline.pixels())
+        .zip(diff_image.pixels_mut())
+    {
+        if pixel == baseline_pixel {
+            let [red, green, blue, _] = pixel.0;
+            let luma = ((red as u32 * 299 + green as u32 * 587 + blue as u32 * 114) / 1000) as u8;
+            let dimmed = 64 + luma / 4;
+            *diff_pixel = Rgba([dimmed, dimmed, dimmed, 255]);
+        } else {
+            differing_pixels += 1;
+            *diff_pixel = Rgba([255, 0, 0, 255]);
+        }
+    }
+
+    Ok(ImageDiff {
+        differing_pixels,
+        total_pixels: image.width() as u64 * image.height() as u64,
+        diff_image,
+    })
+}
+
+/// Characters used for the terminal preview, ordered from dark to bright.
+const PREVIEW_RAMP: &[u8] = b" .:-=+*#%@";
+
+/// Render a downscaled luma version of the frame as ASCII art with the given number of columns.
+/// Terminal cells are about twice as high as wide, so every line covers two pixel rows.
+pub fn render_terminal_preview(frame: &Frame, columns: u32) -> Result<String> {
+    let image = frame.to_rgba_image()?;
+    let columns = columns.clamp(1, image.width().max(1));
+    let lines = ((image.height() as u64 * columns as u64) / (image.width().max(1) as u64 * 2))
+        .max(1) as u32;
+
+    let luma = DynamicImage::ImageRgba8(image)
+        .resize_exact(columns, lines, FilterType::Triangle)
+        .to_luma8();
+
+    let mut preview = String::with_capacity(((columns + 1) * lines) as usize);
+    for row in luma.rows() {
+        for pixel in row {
+            let index = pixel.0[0] as usize * (PREVIEW_RAMP.len() - 1) / 255;
+            preview.push(PREVIEW_RAMP[index] as char);
+        }
+        preview.push('\n');
+    }
+
+    Ok(preview)
+}
+
+/// Width of the terminal in columns as exported by the shell, or 80.
+pub fn terminal_columns() -> u32 {
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .unwrap_or(80)
+}
+
+/// Environment variable that sets the default directory for screenshots.
+pub const SCREENSHOT_DIRECTORY_ENV: &str = "SCRCAP_DIR";
+
 pub fn get_screenshot_directory() -> Result<String> {
-    // First try to use XDG_PICTURES_DIR.
+    // First try to use SCRCAP_DIR.
+    // If that fails use XDG_PICTURES_DIR.
     // If that fails use home directory.
     // If that fails use the current directory
+    if let Some(directory) = env::var_os(SCREENSHOT_DIRECTORY_ENV).filter(|dir| !dir.is_empty()) {
+        return Ok(directory.to_string_lossy().into());
+    }
+
     Ok(dirs::picture_dir()
         .unwrap_or(dirs::home_dir().unwrap_or(env::current_dir()?))
         .to_string_lossy()
@@ -0,0 +1,76 @@
+use super::{region_placement_error, Frame, FrameDescription, Output, Platform, Region};
+use anyhow::{bail, Result};
+use image::imageops::{self, FilterType};
+use image::{ColorType, RgbaImage};
+use log::debug;
+
+/// Capture a region that is not contained by a single output. Every output that intersects the
+/// region captures its part, which then get composed into one frame.
+pub fn capture_region_from_outputs<P: Platform + ?Sized>(
+    platform: &mut P,
+    region: Region,
+    outputs: &[Output],
+    overlay_cursor: bool,
+) -> Result<Frame> {
+    let mut parts = Vec::new();
+    for output in outputs {
+        if let Some(part) = output.region().intersection(region) {
+            debug!(
+                "Capture part {:?} of region on output {}",
+                part, output.name
+            );
+            let frame = platform.capture_frame(output, overlay_cursor, Some(part))?;
+            parts.push((part, frame));
+        }
+    }
+
+    if parts.is_empty() {
+        return Err(region_placement_error(region, outputs));
+    }
+
+    composite_frames(region, &parts)
+}
+
+/// Compose the frames of parts of a region into one frame of the whole region. Area not
+/// covered by any part stays transparent. If the parts were captured with different scales, the
+/// canvas uses the highest one and all other parts get upscaled.
+pub fn composite_frames(region: Region, parts: &[(Region, Frame)]) -> Result<Frame> {
+    let Some((_, first_frame)) = parts.first() else {
+        bail!("No frames to compose");
+    };
+
+    let scale = parts
+        .iter()
+        .map(|(part, frame)| frame.frame_format.width as f64 / part.width as f64)
+        .fold(1.0, f64::max);
+    let scaled = |value: i32| (value as f64 * scale).round() as u32;
+    debug!("Compose {} frames with scale {}", parts.len(), scale);
+
+    let mut canvas = RgbaImage::new(scaled(region.width), scaled(region.height));
+    for (part, frame) in parts {
+        let mut image = frame.to_rgba_image()?;
+        let (width, height) = (scaled(part.width), scaled(part.height));
+        if image.dimensions() != (width, height) {
+            image = imageops::resize(&image, width, height, FilterType::Triangle);
+        }
+
+        imageops::replace(
+            &mut canvas,
+            &image,
+            scaled(part.x - region.x) as i64,
+            scaled(part.y - region.y) as i64,
+        );
+    }
+
+    let (width, height) = canvas.dimensions();
+    Frame::from_bytes(
+        FrameDescription {
+            format: first_frame.frame_format.format,
+            width,
+            height,
+            stride: width * 4,
+        },
+        ColorType::Rgba8,
+        &canvas,
+    )
+}
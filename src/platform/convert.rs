@@ -15,16 +15,43 @@ struct ConvertNone {}
 #[derive(Default)]
 struct ConvertRGB8 {}
 
+/// Undoes the premultiplication of the color channels with alpha after converting to Rgba8
+struct Unpremultiply {
+    converter: Box<dyn Convert>,
+}
+
 const SHIFT10BITS_1: u32 = 20;
 const SHIFT10BITS_2: u32 = 10;
 
 /// Creates format converter based of input format, return None if conversion
 /// isn't possible. Conversion is happening inplace.
 pub fn create_converter(format: FrameFormat) -> Box<dyn Convert> {
-    match format {
+    let converter: Box<dyn Convert> = match format {
         FrameFormat::Xbgr8888 | FrameFormat::Abgr8888 => Box::new(ConvertNone::default()),
         FrameFormat::Xrgb8888 | FrameFormat::Argb8888 => Box::new(ConvertRGB8::default()),
         FrameFormat::Xbgr2101010 | FrameFormat::Abgr2101010 => Box::new(ConvertBGR10::default()),
+    };
+    // The 10-bit converter drops the 2-bit alpha channel, so only 8-bit alpha is kept
+    if format.is_premultiplied() && format != FrameFormat::Abgr2101010 {
+        Box::new(Unpremultiply { converter })
+    } else {
+        converter
+    }
+}
+
+impl Convert for Unpremultiply {
+    fn convert_inplace(&self, data: &mut [u8]) -> ColorType {
+        let color_type = self.converter.convert_inplace(data);
+        for chunk in data.chunks_exact_mut(4) {
+            let alpha = chunk[3] as u32;
+            if alpha == 0 || alpha == 255 {
+                continue;
+            }
+            for color in &mut chunk[..3] {
+                *color = ((*color as u32 * 255 + alpha / 2) / alpha).min(255) as u8;
+            }
+        }
+        color_type
     }
 }
 
@@ -43,6 +70,76 @@ impl Convert for ConvertRGB8 {
     }
 }
 
+/// Convert the rows of a frame inplace. Only the first `width` pixels of every row get
+/// converted, so the padding of an aligned stride is left untouched and can not shift the
+/// pixels of the following rows.
+pub fn convert_rows_inplace(
+    converter: &dyn Convert,
+    data: &mut [u8],
+    width: u32,
+    stride: u32,
+) -> ColorType {
+    let row_bytes = width as usize * 4;
+    let stride = stride as usize;
+    if stride <= row_bytes {
+        return converter.convert_inplace(data);
+    }
+    let mut color_type = converter.convert_inplace(&mut []);
+    for row in data.chunks_mut(stride) {
+        let row_bytes = row_bytes.min(row.len());
+        color_type = converter.convert_inplace(&mut row[..row_bytes]);
+    }
+    color_type
+}
+
+/// Convert raw image data into Rgba16 samples in native byte order. 10-bit formats keep their
+/// full precision, 8-bit formats get scaled up.
+pub fn convert_to_rgba16(format: FrameFormat, data: &[u8]) -> Vec<u8> {
+    let mut converted = Vec::with_capacity(data.len() * 2);
+    match format {
+        FrameFormat::Xbgr2101010 | FrameFormat::Abgr2101010 => {
+            for chunk in data.chunks_exact(4) {
+                let pixel = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                let alpha = if format == FrameFormat::Abgr2101010 {
+                    (pixel >> 30) as u16 * 0x5555
+                } else {
+                    u16::MAX
+                };
+                // Undo the premultiplication with alpha
+                let color = |color: u32| {
+                    let color = convert10_to_16(color) as u32;
+                    match alpha {
+                        0 | u16::MAX => color as u16,
+                        alpha => (color * 0xffff / alpha as u32).min(0xffff) as u16,
+                    }
+                };
+                for sample in [
+                    color(pixel),
+                    color(pixel >> SHIFT10BITS_2),
+                    color(pixel >> SHIFT10BITS_1),
+                    alpha,
+                ] {
+                    converted.extend_from_slice(&sample.to_ne_bytes());
+                }
+            }
+        }
+        _ => {
+            let mut data = data.to_vec();
+            create_converter(format).convert_inplace(&mut data);
+            for sample in data {
+                converted.extend_from_slice(&(sample as u16 * 257).to_ne_bytes());
+            }
+        }
+    }
+    converted
+}
+
+/// Scale 10 bits of one channel to 16 bits, so that the maximum stays the maximum
+fn convert10_to_16(color: u32) -> u16 {
+    let color = (color & 1023) as u16;
+    (color << 6) | (color >> 4)
+}
+
 /// Simple conversion from 10 to 8 bits for one channel
 fn convert10_to_8(color: u32) -> u8 {
     ((color >> 2) & 255) as u8
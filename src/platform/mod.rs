@@ -1,13 +1,38 @@
-mod convert;
+mod composite;
+pub mod convert;
+#[cfg(feature = "async")]
+mod nonblocking;
+mod stream;
 mod sway;
 
-use anyhow::Result;
-use image::ColorType;
+use crate::error::ScrcapError;
+use anyhow::{bail, Result};
+use image::imageops::{self, FilterType};
+use image::{ColorType, DynamicImage, ImageBuffer, Pixel, Rgba, RgbaImage};
 use memmap2::MmapMut;
+use nix::errno::Errno;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Write};
+use std::os::fd::RawFd;
+use std::str::FromStr;
+use std::time::Duration;
 
 use self::sway::PlatformWayland;
 
-#[derive(Debug, Default, Clone, Copy)]
+pub use self::composite::{capture_region_from_outputs, composite_frames};
+#[cfg(feature = "async")]
+pub use self::nonblocking::{capture_frame_async, capture_frame_async_with};
+pub use self::stream::capture_stream;
+
+/// Log target of the trace messages with the raw output events received during output discovery
+pub const OUTPUT_DISCOVERY_LOG_TARGET: &str = "scrcap::output_discovery";
+/// Log target of the trace messages comparing the stride advertised by the compositor with the
+/// stride of packed rows
+pub const STRIDE_LOG_TARGET: &str = "scrcap::stride";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct Region {
     pub x: i32,
     pub y: i32,
@@ -31,6 +56,59 @@ impl Region {
             && (other.x + other.width) <= (self.x + self.width)
             && (other.y + other.height) <= (self.y + self.height)
     }
+
+    /// Check if both regions overlap. Regions that only touch do not overlap.
+    pub fn intersects(&self, other: Region) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    /// Number of pixels in the region. Regions with negative size have no area.
+    pub fn area(&self) -> i64 {
+        self.width.max(0) as i64 * self.height.max(0) as i64
+    }
+
+    /// Return the overlapping part of both regions or None if they do not overlap
+    pub fn intersection(&self, other: Region) -> Option<Region> {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x + self.width).min(other.x + other.width);
+        let bottom = (self.y + self.height).min(other.y + other.height);
+        if right <= x || bottom <= y {
+            return None;
+        }
+
+        Some(Region::new(x, y, right - x, bottom - y))
+    }
+}
+
+impl fmt::Display for Region {
+    /// Format as `X,Y WxH`, the geometry format that is parsed by `from_str`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{} {}x{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+impl FromStr for Region {
+    type Err = anyhow::Error;
+
+    /// Parse a geometry given as `X,Y WxH`, the format used by slurp
+    fn from_str(value: &str) -> Result<Self> {
+        let Some((position, size)) = value.trim().split_once(' ') else {
+            bail!("Geometry must be given as X,Y WxH, e.g. 0,0 1920x1080");
+        };
+        let (Some((x, y)), Some((width, height))) =
+            (position.split_once(','), size.trim().split_once('x'))
+        else {
+            bail!("Geometry must be given as X,Y WxH, e.g. 0,0 1920x1080");
+        };
+
+        Ok(Self::new(
+            x.trim().parse()?,
+            y.trim().parse()?,
+            width.trim().parse()?,
+            height.trim().parse()?,
+        ))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -41,6 +119,149 @@ pub struct Output {
     pub width: i32,
     pub height: i32,
     pub scale: i32,
+    /// Physical width of the panel in millimeters, 0 if unknown, e.g. for projectors
+    pub physical_width: i32,
+    /// Physical height of the panel in millimeters, 0 if unknown
+    pub physical_height: i32,
+}
+
+impl Output {
+    /// Pixel density in pixels per meter horizontally and vertically, derived from the physical
+    /// size. None if the output does not report its physical size.
+    pub fn pixels_per_meter(&self) -> Option<(u32, u32)> {
+        if self.width <= 0 || self.height <= 0 {
+            return None;
+        }
+        if self.physical_width <= 0 || self.physical_height <= 0 {
+            return None;
+        }
+        // The physical size is the one of the panel, which may be rotated
+        let (physical_width, physical_height) =
+            if (self.width > self.height) == (self.physical_width > self.physical_height) {
+                (self.physical_width, self.physical_height)
+            } else {
+                (self.physical_height, self.physical_width)
+            };
+        let pixels_per_meter = |pixels: i32, millimeters: i32| {
+            (pixels as i64 * self.scale.max(1) as i64 * 1000 / millimeters as i64) as u32
+        };
+        Some((
+            pixels_per_meter(self.width, physical_width),
+            pixels_per_meter(self.height, physical_height),
+        ))
+    }
+
+    /// Area of the output in global coordinates
+    pub fn region(&self) -> Region {
+        Region::new(self.x, self.y, self.width, self.height)
+    }
+
+    /// Whether the point given in global coordinates lies on the output
+    pub fn contains_point(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+
+    /// Convert a region in physical pixels relative to the output, i.e. in the pixels of a
+    /// screenshot of the whole output, to global logical coordinates. Partially covered
+    /// logical pixels are included.
+    pub fn logical_region(&self, physical: Region) -> Region {
+        let scale = self.scale.max(1);
+        let left = physical.x.div_euclid(scale);
+        let top = physical.y.div_euclid(scale);
+        let right = (physical.x + physical.width + scale - 1).div_euclid(scale);
+        let bottom = (physical.y + physical.height + scale - 1).div_euclid(scale);
+        Region::new(self.x + left, self.y + top, right - left, bottom - top)
+    }
+}
+
+/// Explain running out of memory for a frame, which happens for huge captures on machines with
+/// little memory. Other errors are passed on as they are.
+fn frame_memory_error(err: io::Error, frame_format: &FrameDescription) -> anyhow::Error {
+    let out_of_memory = [Errno::ENOMEM, Errno::ENOSPC]
+        .iter()
+        .any(|errno| err.raw_os_error() == Some(*errno as i32));
+    if !out_of_memory {
+        return err.into();
+    }
+    ScrcapError::CaptureFailed(format!(
+        "Not enough memory for a {}x{} frame of {} MiB ({}). Capture a smaller region with -x, -y, -w and -H or a single output",
+        frame_format.width,
+        frame_format.height,
+        (frame_format.stride as u64 * frame_format.height as u64).div_ceil(1024 * 1024),
+        err
+    ))
+    .into()
+}
+
+/// Reverse the order of the rows of pixel data in place
+fn flip_rows(data: &mut [u8], stride: u32, height: u32) {
+    let stride = stride as usize;
+    let height = (height as usize).min(data.len() / stride.max(1));
+    for row in 0..height / 2 {
+        let (top, bottom) = data.split_at_mut((height - 1 - row) * stride);
+        top[row * stride..(row + 1) * stride].swap_with_slice(&mut bottom[..stride]);
+    }
+}
+
+/// Find the output that contains the point given in global coordinates
+pub fn output_at_point(outputs: &[Output], x: i32, y: i32) -> Option<&Output> {
+    outputs.iter().find(|output| output.contains_point(x, y))
+}
+
+/// Explain why the region does not lie on a single output. For a region that is off screen the
+/// nearest output and its bounds are named.
+pub fn region_placement_error(region: Region, outputs: &[Output]) -> anyhow::Error {
+    let bounds = |output: &Output| {
+        format!(
+            "x {} to {} and y {} to {}",
+            output.x,
+            output.x + output.width,
+            output.y,
+            output.y + output.height
+        )
+    };
+    let distance = |output: &Output| {
+        let output = output.region();
+        let dx = (output.x - (region.x + region.width))
+            .max(region.x - (output.x + output.width))
+            .max(0) as i64;
+        let dy = (output.y - (region.y + region.height))
+            .max(region.y - (output.y + output.height))
+            .max(0) as i64;
+        dx * dx + dy * dy
+    };
+
+    let overlapping = outputs
+        .iter()
+        .filter(|output| output.region().intersects(region))
+        .collect::<Vec<_>>();
+    let message = match overlapping.as_slice() {
+        [] => match outputs.iter().min_by_key(|output| distance(output)) {
+            Some(nearest) => format!(
+                "Region {} is outside of all outputs. The nearest output {} covers {}",
+                region,
+                nearest.name,
+                bounds(nearest)
+            ),
+            None => format!("Region {} can not be captured without outputs", region),
+        },
+        [output] => format!(
+            "Region {} exceeds output {}, which covers {}",
+            region,
+            output.name,
+            bounds(output)
+        ),
+        outputs => format!(
+            "Region {} spans the outputs {}, but must lie on a single output",
+            region,
+            outputs
+                .iter()
+                .map(|output| output.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    ScrcapError::InvalidRegion(message).into()
 }
 
 impl Default for Output {
@@ -52,11 +273,179 @@ impl Default for Output {
             width: 0,
             height: 0,
             scale: 1,
+            physical_width: 0,
+            physical_height: 0,
         }
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+/// Compositor and session a platform is connected to, embedded into screenshots to help with
+/// bug reports.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SessionInfo {
+    /// Name of the compositor followed by its version if known, e.g. `sway 1.8.1`
+    pub compositor: Option<String>,
+    /// Name of the Wayland socket, e.g. `wayland-1`
+    pub wayland_socket: Option<String>,
+}
+
+impl SessionInfo {
+    /// Metadata entries as keyword and text, starting with the version of scrcap
+    pub fn metadata(&self) -> Vec<(String, String)> {
+        let mut metadata = vec![(
+            "Software".to_string(),
+            format!("scrcap {}", env!("CARGO_PKG_VERSION")),
+        )];
+        if let Some(compositor) = &self.compositor {
+            metadata.push(("Compositor".into(), compositor.clone()));
+        }
+        if let Some(wayland_socket) = &self.wayland_socket {
+            metadata.push(("Wayland socket".into(), wayland_socket.clone()));
+        }
+        metadata
+    }
+}
+
+/// Version of scrcap and the versions of the Wayland protocols negotiated with the compositor,
+/// for support requests. A protocol is `None` if the compositor does not offer it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct VersionInfo {
+    pub screencopy: Option<u32>,
+    pub xdg_output: Option<u32>,
+    pub wl_shm: Option<u32>,
+    /// Whether the sway IPC socket could be reached
+    pub swayipc: bool,
+}
+
+impl fmt::Display for VersionInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "scrcap {}", env!("CARGO_PKG_VERSION"))?;
+        for (protocol, version) in [
+            ("zwlr_screencopy_manager_v1", self.screencopy),
+            ("zxdg_output_manager_v1", self.xdg_output),
+            ("wl_shm", self.wl_shm),
+        ] {
+            match version {
+                Some(version) => writeln!(f, "{}: version {}", protocol, version)?,
+                None => writeln!(f, "{}: not available", protocol)?,
+            }
+        }
+        let swayipc = if self.swayipc {
+            "available"
+        } else {
+            "not available"
+        };
+        writeln!(f, "swayipc: {}", swayipc)
+    }
+}
+
+/// Time spent in the phases of taking a screenshot. The platform measures connecting to the
+/// compositor and discovering the outputs once, capturing and converting add up over all
+/// captures. Encoding is measured by the caller.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Timings {
+    pub connect: Duration,
+    pub discover_outputs: Duration,
+    /// Waiting for the compositor to copy frames
+    pub capture: Duration,
+    /// Converting frames into a format that can be encoded
+    pub convert: Duration,
+    pub encode: Duration,
+}
+
+/// Image with 16 bits per channel in native byte order, the layout of Rgba16 frames
+pub type Rgba16Image = ImageBuffer<Rgba<u16>, Vec<u16>>;
+
+/// Transform of an output as in wl_output, given clockwise. Flipped transforms mirror the
+/// frame horizontally before rotating it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OutputTransform {
+    Normal,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    Flipped,
+    Flipped90,
+    Flipped180,
+    Flipped270,
+}
+
+impl FromStr for OutputTransform {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "normal" => Ok(OutputTransform::Normal),
+            "90" => Ok(OutputTransform::Rotate90),
+            "180" => Ok(OutputTransform::Rotate180),
+            "270" => Ok(OutputTransform::Rotate270),
+            "flipped" => Ok(OutputTransform::Flipped),
+            "flipped-90" => Ok(OutputTransform::Flipped90),
+            "flipped-180" => Ok(OutputTransform::Flipped180),
+            "flipped-270" => Ok(OutputTransform::Flipped270),
+            _ => bail!("Unknown output transform {}", value),
+        }
+    }
+}
+
+/// Rotate and flip the image by the transform
+pub fn transform_image<P: Pixel + 'static>(
+    image: ImageBuffer<P, Vec<P::Subpixel>>,
+    transform: OutputTransform,
+) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    let image = match transform {
+        OutputTransform::Flipped
+        | OutputTransform::Flipped90
+        | OutputTransform::Flipped180
+        | OutputTransform::Flipped270 => imageops::flip_horizontal(&image),
+        _ => image,
+    };
+    match transform {
+        OutputTransform::Normal | OutputTransform::Flipped => image,
+        OutputTransform::Rotate90 | OutputTransform::Flipped90 => imageops::rotate90(&image),
+        OutputTransform::Rotate180 | OutputTransform::Flipped180 => imageops::rotate180(&image),
+        OutputTransform::Rotate270 | OutputTransform::Flipped270 => imageops::rotate270(&image),
+    }
+}
+
+/// Region of the image without the uniform borders around its content. The color of the top
+/// left pixel is the border color, pixels that differ in no channel by more than the tolerance
+/// count as border. `None` if the whole image has the border color.
+pub fn image_content_region(image: &RgbaImage, tolerance: u8) -> Option<Region> {
+    let &border = image.pixels().next()?;
+    let is_content = |x: u32, y: u32| {
+        image
+            .get_pixel(x, y)
+            .0
+            .iter()
+            .zip(border.0)
+            .any(|(&channel, border)| channel.abs_diff(border) > tolerance)
+    };
+    let row_has_content = |y: u32| (0..image.width()).any(|x| is_content(x, y));
+
+    let top = (0..image.height()).find(|&y| row_has_content(y))?;
+    let bottom = (top..image.height())
+        .rev()
+        .find(|&y| row_has_content(y))
+        .unwrap_or(top);
+    let column_has_content = |x: u32| (top..=bottom).any(|y| is_content(x, y));
+    let left = (0..image.width())
+        .find(|&x| column_has_content(x))
+        .unwrap_or(0);
+    let right = (left..image.width())
+        .rev()
+        .find(|&x| column_has_content(x))
+        .unwrap_or(left);
+
+    Some(Region::new(
+        left as i32,
+        top as i32,
+        (right - left + 1) as i32,
+        (bottom - top + 1) as i32,
+    ))
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
 pub enum FrameFormat {
     Xbgr2101010,
     Xrgb8888,
@@ -66,7 +455,34 @@ pub enum FrameFormat {
     Argb8888,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+impl FrameFormat {
+    /// Whether the format has an alpha channel. As for every wl_shm buffer, the color channels
+    /// are premultiplied with it. Screencopy has no flag that says otherwise.
+    pub fn is_premultiplied(&self) -> bool {
+        matches!(
+            self,
+            FrameFormat::Abgr2101010 | FrameFormat::Abgr8888 | FrameFormat::Argb8888
+        )
+    }
+}
+
+impl FromStr for FrameFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "xbgr2101010" => Ok(FrameFormat::Xbgr2101010),
+            "xrgb8888" => Ok(FrameFormat::Xrgb8888),
+            "xbgr8888" => Ok(FrameFormat::Xbgr8888),
+            "abgr2101010" => Ok(FrameFormat::Abgr2101010),
+            "abgr8888" => Ok(FrameFormat::Abgr8888),
+            "argb8888" => Ok(FrameFormat::Argb8888),
+            _ => bail!("Unknown frame format {}", value),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
 pub struct FrameDescription {
     pub format: FrameFormat,
     pub width: u32,
@@ -78,8 +494,225 @@ pub struct Frame {
     pub frame_format: FrameDescription,
     pub frame_mmap: MmapMut,
     pub frame_color_type: ColorType,
+    /// Areas of the frame that changed since the last capture, relative to the frame. Only
+    /// filled by `Platform::capture_frame_with_damage`.
+    pub damage: Vec<Region>,
+}
+
+impl Frame {
+    /// Create a frame by copying the given pixel data into a new memory map
+    pub fn from_bytes(
+        frame_format: FrameDescription,
+        frame_color_type: ColorType,
+        data: &[u8],
+    ) -> Result<Self> {
+        let frame_bytes = (frame_format.stride * frame_format.height) as usize;
+        if frame_bytes == 0 || data.len() < frame_bytes {
+            bail!(
+                "Expected {} bytes of frame data, got {}",
+                frame_bytes,
+                data.len()
+            );
+        }
+
+        let mut frame_mmap =
+            MmapMut::map_anon(frame_bytes).map_err(|err| frame_memory_error(err, &frame_format))?;
+        frame_mmap.copy_from_slice(&data[..frame_bytes]);
+
+        Ok(Self {
+            frame_format,
+            frame_mmap,
+            frame_color_type,
+            damage: Vec::new(),
+        })
+    }
+
+    /// Reverse the order of the rows, e.g. of a frame the compositor sent y-inverted
+    pub fn flip_vertically(&mut self) {
+        flip_rows(
+            &mut self.frame_mmap,
+            self.frame_format.stride,
+            self.frame_format.height,
+        );
+    }
+
+    /// Pixel data without the row padding of the stride. Only copies if the rows are padded.
+    pub fn packed_data(&self) -> Result<Cow<'_, [u8]>> {
+        let row_bytes =
+            self.frame_format.width as usize * self.frame_color_type.bytes_per_pixel() as usize;
+        let stride = self.frame_format.stride as usize;
+        let height = self.frame_format.height as usize;
+        if stride < row_bytes || self.frame_mmap.len() < stride * height {
+            bail!("Frame is smaller than its description");
+        }
+
+        if stride == row_bytes {
+            return Ok(Cow::Borrowed(&self.frame_mmap[..row_bytes * height]));
+        }
+        let mut data = Vec::with_capacity(row_bytes * height);
+        for row in self.frame_mmap.chunks(stride).take(height) {
+            data.extend_from_slice(&row[..row_bytes]);
+        }
+        Ok(Cow::Owned(data))
+    }
+
+    /// Copy the frame into an image without any row padding. Rgba16 frames get reduced to 8 bits
+    /// per channel, other frames have to be Rgba8.
+    pub fn to_rgba_image(&self) -> Result<RgbaImage> {
+        if self.frame_color_type == ColorType::Rgba16 {
+            return Ok(DynamicImage::ImageRgba16(self.to_rgba16_image()?).into_rgba8());
+        }
+        if self.frame_color_type != ColorType::Rgba8 {
+            bail!(
+                "Can not convert {:?} frame into an Rgba8 image",
+                self.frame_color_type
+            );
+        }
+
+        let data = self.packed_data()?.into_owned();
+        match RgbaImage::from_raw(self.frame_format.width, self.frame_format.height, data) {
+            Some(image) => Ok(image),
+            None => bail!("Frame is smaller than its description"),
+        }
+    }
+
+    /// Copy an Rgba16 frame into an image without any row padding
+    pub fn to_rgba16_image(&self) -> Result<Rgba16Image> {
+        if self.frame_color_type != ColorType::Rgba16 {
+            bail!(
+                "Can not convert {:?} frame into an Rgba16 image",
+                self.frame_color_type
+            );
+        }
+
+        let samples = self
+            .packed_data()?
+            .chunks_exact(2)
+            .map(|sample| u16::from_ne_bytes([sample[0], sample[1]]))
+            .collect();
+        match Rgba16Image::from_raw(self.frame_format.width, self.frame_format.height, samples) {
+            Some(image) => Ok(image),
+            None => bail!("Frame is smaller than its description"),
+        }
+    }
+
+    /// Create an Rgba16 frame from an image, e.g. after processing the result of
+    /// `to_rgba16_image`. The format is the one of the frame the image was made from.
+    pub fn from_rgba16_image(format: FrameFormat, image: &Rgba16Image) -> Result<Frame> {
+        let data = image
+            .as_raw()
+            .iter()
+            .flat_map(|sample| sample.to_ne_bytes())
+            .collect::<Vec<_>>();
+        Frame::from_bytes(
+            FrameDescription {
+                format,
+                width: image.width(),
+                height: image.height(),
+                stride: image.width() * 8,
+            },
+            ColorType::Rgba16,
+            &data,
+        )
+    }
+
+    /// Create an Rgba8 frame from an image, e.g. after processing the result of `to_rgba_image`.
+    /// The format is the one of the frame the image was made from.
+    pub fn from_rgba_image(format: FrameFormat, image: &RgbaImage) -> Result<Frame> {
+        Frame::from_bytes(
+            FrameDescription {
+                format,
+                width: image.width(),
+                height: image.height(),
+                stride: image.width() * 4,
+            },
+            ColorType::Rgba8,
+            image,
+        )
+    }
+
+    /// Convert an unconverted frame as returned by `capture_frame_raw` into an Rgba16 frame
+    /// without row padding. 10-bit formats keep their full precision.
+    pub fn to_rgba16(&self) -> Result<Frame> {
+        let row_bytes = self.frame_format.width as usize * 4;
+        if (self.frame_format.stride as usize) < row_bytes {
+            bail!("Frame stride is smaller than its width");
+        }
+
+        let mut data = Vec::with_capacity(row_bytes * 2 * self.frame_format.height as usize);
+        for row in self
+            .frame_mmap
+            .chunks(self.frame_format.stride as usize)
+            .take(self.frame_format.height as usize)
+        {
+            data.extend(convert::convert_to_rgba16(
+                self.frame_format.format,
+                &row[..row_bytes],
+            ));
+        }
+
+        Frame::from_bytes(
+            FrameDescription {
+                stride: self.frame_format.width * 8,
+                ..self.frame_format
+            },
+            ColorType::Rgba16,
+            &data,
+        )
+    }
+
+    /// Scale the frame to the given width. The height is chosen to preserve the aspect ratio.
+    pub fn resize_to_width(&self, width: u32) -> Result<Frame> {
+        if width == 0 {
+            bail!("Can not resize frame to zero width");
+        }
+        let height = ((self.frame_format.height as u64 * width as u64)
+            / self.frame_format.width.max(1) as u64)
+            .max(1) as u32;
+
+        let image = imageops::resize(&self.to_rgba_image()?, width, height, FilterType::Triangle);
+        Frame::from_rgba_image(self.frame_format.format, &image)
+    }
+
+    /// Copy the given region of the frame into a new frame. The region is relative to the top
+    /// left corner of the frame.
+    pub fn crop(&self, region: Region) -> Result<Frame> {
+        let frame_region = Region::new(
+            0,
+            0,
+            self.frame_format.width as i32,
+            self.frame_format.height as i32,
+        );
+        if region.width <= 0 || region.height <= 0 || !frame_region.contains(region) {
+            bail!("Crop region {:?} is outside of the frame", region);
+        }
+
+        let bytes_per_pixel = self.frame_color_type.bytes_per_pixel() as usize;
+        let src_stride = self.frame_format.stride as usize;
+        let row_bytes = region.width as usize * bytes_per_pixel;
+        let offset = region.x as usize * bytes_per_pixel;
+
+        let mut data = Vec::with_capacity(row_bytes * region.height as usize);
+        for row in region.y as usize..(region.y + region.height) as usize {
+            let start = row * src_stride + offset;
+            data.extend_from_slice(&self.frame_mmap[start..start + row_bytes]);
+        }
+
+        Frame::from_bytes(
+            FrameDescription {
+                format: self.frame_format.format,
+                width: region.width as u32,
+                height: region.height as u32,
+                stride: row_bytes as u32,
+            },
+            self.frame_color_type,
+            &data,
+        )
+    }
 }
 
+/// Outputs and regions are given in global logical coordinates, as reported by xdg-output. The
+/// captured frames have the physical size of the region, i.e. it gets multiplied by the scale.
 pub trait Platform {
     fn outputs(&self) -> Vec<Output>;
 
@@ -90,9 +723,104 @@ pub trait Platform {
         region: Option<Region>,
     ) -> Result<Frame>;
 
+    /// Capture a frame with the pixel data exactly as sent by the compositor. The layout of the
+    /// data is given by the frame format, `frame_color_type` only tells the size of a pixel.
+    fn capture_frame_raw(
+        &mut self,
+        output: &Output,
+        overlay_cursor: bool,
+        region: Option<Region>,
+    ) -> Result<Frame>;
+
+    /// Capture a frame into a sealed shared memory file without copying or converting it. The
+    /// pixel data has the layout of the returned description. The caller owns the file
+    /// descriptor and is responsible for closing it, e.g. after passing it to another process.
+    fn capture_frame_fd(
+        &mut self,
+        output: &Output,
+        overlay_cursor: bool,
+        region: Option<Region>,
+    ) -> Result<(RawFd, FrameDescription)>;
+
+    /// Wait until the output changed and capture a frame. The changed areas are reported in
+    /// the damage of the frame.
+    fn capture_frame_with_damage(
+        &mut self,
+        output: &Output,
+        overlay_cursor: bool,
+        region: Option<Region>,
+    ) -> Result<Frame>;
+
+    /// Compositor and session the platform is connected to, as far as they are known
+    fn session_info(&self) -> SessionInfo {
+        SessionInfo::default()
+    }
+
+    /// Versions of the protocols negotiated when the platform was created
+    fn version_info(&self) -> VersionInfo {
+        VersionInfo::default()
+    }
+
+    /// Time spent in the phases of the platform so far
+    fn timings(&self) -> Timings {
+        Timings::default()
+    }
+
+    /// Output that has the focus, i.e. the screen a keybinding was pressed on. Fails on
+    /// platforms that do not know it.
+    fn focused_output(&self) -> Result<Output> {
+        bail!("The focused output is unknown on this platform")
+    }
+
+    /// Only capture frames in the given format. Captures fail if the compositor does not offer
+    /// it, instead of falling back to another format.
+    fn set_required_format(&mut self, format: Option<FrameFormat>);
+
     fn focused_window_area(&self) -> Result<Region>;
+
+    /// Name of the focused window, its app_id or X11 class, falling back to its title
+    fn focused_window_name(&self) -> Result<String>;
+
+    /// Wait until a window with the given app_id or a title containing name exists and return
+    /// its area
+    fn wait_for_window(&self, name: &str, timeout: Duration) -> Result<Region>;
+
+    /// Area of the window carrying the given mark. Fails if no window has the mark or it is not
+    /// visible, e.g. hidden in the scratchpad.
+    fn marked_window_area(&self, mark: &str) -> Result<Region>;
+
+    /// Area of the workspace with the given name or number. Fails if the workspace is not
+    /// visible on any output.
+    fn workspace_area(&self, name: &str) -> Result<Region>;
+
+    /// Capture a region given in global coordinates. The outputs the region belongs to are
+    /// resolved internally and, if the region spans several of them, composed into one frame.
+    fn capture_region_global(&mut self, region: Region, overlay_cursor: bool) -> Result<Frame> {
+        let outputs = self.outputs();
+        let output = outputs
+            .iter()
+            .find(|output| output.region().contains(region));
+
+        match output {
+            Some(output) => self.capture_frame(output, overlay_cursor, Some(region)),
+            None => capture_region_from_outputs(self, region, &outputs, overlay_cursor),
+        }
+    }
 }
 
 pub fn create_platform() -> Result<Box<dyn Platform>> {
     Ok(Box::new(PlatformWayland::new()?))
 }
+
+/// Outputs of the compositor with their global logical position, size and scale, e.g. to build
+/// an output picker. Every call connects to the compositor on its own, so it can be called from
+/// any thread. The platform itself can not be moved between threads, but the returned outputs
+/// are plain data that can be.
+pub fn outputs() -> Result<Vec<Output>> {
+    Ok(create_platform()?.outputs())
+}
+
+/// Write a report about the capabilities of the platform, e.g. for bug reports
+pub fn diagnose(report: &mut dyn Write) -> Result<()> {
+    sway::diagnose(report)
+}
@@ -0,0 +1,30 @@
+use super::{create_platform, Frame, Output, Platform, Region};
+use anyhow::{Context, Result};
+
+/// Capture a frame without blocking the async runtime. The platform gets created and used on
+/// the blocking thread pool of tokio, as it can not be moved between threads.
+pub async fn capture_frame_async(
+    output: Output,
+    overlay_cursor: bool,
+    region: Option<Region>,
+) -> Result<Frame> {
+    capture_frame_async_with(create_platform, output, overlay_cursor, region).await
+}
+
+/// Like `capture_frame_async`, but with a custom function to create the platform
+pub async fn capture_frame_async_with<F>(
+    create_platform: F,
+    output: Output,
+    overlay_cursor: bool,
+    region: Option<Region>,
+) -> Result<Frame>
+where
+    F: FnOnce() -> Result<Box<dyn Platform>> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || {
+        let mut platform = create_platform()?;
+        platform.capture_frame(&output, overlay_cursor, region)
+    })
+    .await
+    .context("Capture task failed")?
+}
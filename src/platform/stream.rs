@@ -0,0 +1,43 @@
+use super::{Frame, Output, Platform, Region};
+use anyhow::Result;
+use log::debug;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Capture `count` frames of the output, or of the region on it, one every `interval`, and pass
+/// each of them to the callback. All frames are captured over the connection of the platform.
+///
+/// The callback runs on the capture thread between two captures, so a callback that takes
+/// longer than the interval delays the next capture. An error returned by the callback stops the
+/// stream and is returned.
+pub fn capture_stream<F>(
+    platform: &mut dyn Platform,
+    output: &Output,
+    region: Option<Region>,
+    interval: Duration,
+    count: u32,
+    mut callback: F,
+) -> Result<()>
+where
+    F: FnMut(Frame) -> Result<()>,
+{
+    for index in 0..count {
+        let start = Instant::now();
+        let frame = platform.capture_frame(output, false, region)?;
+        debug!(
+            "Captured frame {} of {} in {:?}",
+            index + 1,
+            count,
+            start.elapsed()
+        );
+        callback(frame)?;
+
+        if index + 1 < count {
+            if let Some(remaining) = interval.checked_sub(start.elapsed()) {
+                thread::sleep(remaining);
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,44 +1,79 @@
 use std::{
-    cell::RefCell,
+    cell::{OnceCell, RefCell},
     fs::File,
     rc::Rc,
     sync::atomic::{AtomicBool, Ordering},
 };
 
+use crate::error::ScrcapError;
 use crate::platform::FrameDescription;
 
-use super::{convert::create_converter, Frame, FrameFormat, Output, Platform, Region};
-use anyhow::{bail, Context, Result};
-use log::{debug, error, info};
+use super::{
+    convert::{convert_rows_inplace, create_converter},
+    flip_rows, frame_memory_error, Frame, FrameFormat, Output, Platform, Region, SessionInfo,
+    Timings, VersionInfo, OUTPUT_DISCOVERY_LOG_TARGET, STRIDE_LOG_TARGET,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use image::ColorType;
+use log::{debug, error, info, trace, warn};
 use memmap2::MmapMut;
 use nix::sys::{memfd, mman, stat};
 use nix::{fcntl, unistd};
-use std::ffi::CStr;
+use std::env;
+use std::ffi::CString;
+use std::io::Write;
+use std::ops::Deref;
 use std::os::fd::RawFd;
-use std::os::unix::io::FromRawFd;
-use std::time::{SystemTime, UNIX_EPOCH};
-use thiserror::Error;
+use std::os::unix::io::{FromRawFd, IntoRawFd};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use wayland_client::{
     global_filter,
     protocol::{wl_output::WlOutput, wl_shm},
-    Display, EventQueue, GlobalManager, Main,
+    Display, EventQueue, GlobalManager, Interface, Main,
 };
 use wayland_protocols::{
     unstable::xdg_output::v1::client::zxdg_output_manager_v1::ZxdgOutputManagerV1,
-    wlr::unstable::screencopy::v1::client::zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+    wlr::unstable::screencopy::v1::client::{
+        zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1,
+        zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+    },
 };
 
 const WL_OUTPUT_VERSION: u32 = 4;
+/// Highest version of wl_shm known to the Wayland bindings
+const WL_SHM_VERSION: u32 = 1;
+/// Lowest version of the xdg-output manager that is used. Version 2 added the name event.
+const XDG_OUTPUT_MANAGER_MIN_VERSION: u32 = 2;
+/// Highest supported version of the xdg-output manager
+const XDG_OUTPUT_MANAGER_VERSION: u32 = 3;
+/// Highest supported version of the screencopy protocol. Version 3 added the buffer_done event.
+const SCREENCOPY_MANAGER_VERSION: u32 = 3;
+/// Name of the shared memory files that receive the frames, shows up in /proc and /dev/shm
+const SHM_NAME_PREFIX: &str = "scrcap";
+/// Number of attempts to connect to the sway IPC socket
+const SWAY_CONNECT_ATTEMPTS: u32 = 3;
+/// Delay before the first retry to connect to sway, increasing with every attempt
+const SWAY_CONNECT_RETRY_DELAY: Duration = Duration::from_millis(100);
+/// Time between two lookups of the window tree while waiting for a window
+const WINDOW_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 pub struct PlatformWayland {
     event_queue: EventQueue,
     globals: GlobalManager,
     screencopy_manager: Main<ZwlrScreencopyManagerV1>,
     outputs: Vec<WaylandOutput>,
+    required_format: Option<FrameFormat>,
+    /// Version of sway, only asked for when needed as it takes a round trip over the sway IPC
+    sway_version: OnceCell<Option<swayipc::Version>>,
+    /// Negotiated protocol versions, the availability of the sway IPC is filled in on request
+    protocol_versions: VersionInfo,
+    timings: Timings,
 }
 
 impl PlatformWayland {
     pub fn new() -> Result<Self> {
+        let start = Instant::now();
         // Connect to the server
         let display = Display::connect_to_env().context("Could not connect to Wayland server")?;
         let mut event_queue = display.create_event_queue();
@@ -54,24 +89,66 @@ impl PlatformWayland {
                     let wayland_outputs = wayland_outputs.clone();
 
                     output_handle.quick_assign(move |output_handle, event, _| {
-                        use wayland_client::protocol::wl_output::Event;
+                        use wayland_client::protocol::wl_output::{Event, Mode};
+                        trace!(
+                            target: OUTPUT_DISCOVERY_LOG_TARGET,
+                            "wl_output@{}: {:?}",
+                            output_handle.as_ref().id(),
+                            event
+                        );
+                        let mut wayland_outputs = wayland_outputs.borrow_mut();
                         match event {
                             Event::Geometry {
-                                x: _,
-                                y: _,
-                                physical_width: _,
-                                physical_height: _,
-                                subpixel: _,
-                                make: _,
-                                model: _,
-                                transform: _,
+                                x,
+                                y,
+                                physical_width,
+                                physical_height,
+                                ..
                             } => {
                                 debug!("Output geometry event");
                                 let wayland_output = WaylandOutput {
                                     raw: output_handle.clone(),
-                                    output: Output::default(),
+                                    output: Output {
+                                        x,
+                                        y,
+                                        physical_width,
+                                        physical_height,
+                                        ..Output::default()
+                                    },
                                 };
-                                wayland_outputs.borrow_mut().push(wayland_output);
+                                wayland_outputs.push(wayland_output);
+                            }
+                            // The following events complete the output. Its geometry is only
+                            // used if the compositor lacks xdg-output.
+                            Event::Mode {
+                                flags,
+                                width,
+                                height,
+                                ..
+                            } if flags.contains(Mode::Current) => {
+                                debug!("Output mode event");
+                                if let Some(output) =
+                                    find_output(&mut wayland_outputs, &output_handle)
+                                {
+                                    output.width = width;
+                                    output.height = height;
+                                }
+                            }
+                            Event::Scale { factor } => {
+                                debug!("Output scale event");
+                                if let Some(output) =
+                                    find_output(&mut wayland_outputs, &output_handle)
+                                {
+                                    output.scale = factor;
+                                }
+                            }
+                            Event::Name { name } => {
+                                debug!("Output name event");
+                                if let Some(output) =
+                                    find_output(&mut wayland_outputs, &output_handle)
+                                {
+                                    output.name = name;
+                                }
                             }
                             _ => (),
                         }
@@ -83,14 +160,91 @@ impl PlatformWayland {
         // A roundtrip synchronization to make sure the server received our registry
         // creation and sent us the global list
         event_queue.sync_roundtrip(&mut (), |_, _, _| unreachable!())?;
+        let connect = start.elapsed();
 
         // Init outputs
         event_queue.sync_roundtrip(&mut (), |_, _, _| ())?;
 
+        // Later events of the outputs are not needed, so the outputs can be moved out
+        let wayland_outputs = wayland_outputs.take();
+        let final_wayland_outputs = match globals.instantiate_range::<ZxdgOutputManagerV1>(
+            XDG_OUTPUT_MANAGER_MIN_VERSION,
+            XDG_OUTPUT_MANAGER_VERSION,
+        ) {
+            Ok(xdg_output_manager) => {
+                Self::xdg_outputs(&mut event_queue, &xdg_output_manager, &wayland_outputs)
+            }
+            Err(err) => {
+                // Without xdg-output only the physical geometry is known. The logical size gets
+                // approximated with the scale, transforms are not taken into account.
+                warn!(
+                    "Compositor does not implement ZxdgOutputManagerV1 ({}), using the geometry of wl_output",
+                    err
+                );
+                wayland_outputs
+                    .into_iter()
+                    .map(|mut wayland_output| {
+                        let output = &mut wayland_output.output;
+                        output.width /= output.scale.max(1);
+                        output.height /= output.scale.max(1);
+                        info!("Found output: {:?}", wayland_output);
+                        wayland_output
+                    })
+                    .collect()
+            }
+        };
+        let discover_outputs = start.elapsed() - connect;
+
+        // Instantiating screencopy manager. Older versions work too, they only lack the
+        // buffer_done event.
+        let screencopy_manager = globals
+            .instantiate_range::<ZwlrScreencopyManagerV1>(1, SCREENCOPY_MANAGER_VERSION)
+            .context(ScrcapError::MissingProtocol(
+                "Failed to create screencopy manager. Does your compositor implement ZwlrScreencopy?"
+                    .into(),
+            ))?;
+        info!(
+            "Using screencopy manager version {}",
+            screencopy_manager.as_ref().version()
+        );
+
+        let protocol_versions = VersionInfo {
+            screencopy: Some(screencopy_manager.as_ref().version()),
+            xdg_output: negotiated_version(
+                &globals,
+                ZxdgOutputManagerV1::NAME,
+                XDG_OUTPUT_MANAGER_MIN_VERSION,
+                XDG_OUTPUT_MANAGER_VERSION,
+            ),
+            wl_shm: negotiated_version(&globals, wl_shm::WlShm::NAME, 1, WL_SHM_VERSION),
+            swayipc: false,
+        };
+
+        Ok(PlatformWayland {
+            event_queue,
+            globals,
+            screencopy_manager,
+            outputs: final_wayland_outputs,
+            required_format: None,
+            sway_version: OnceCell::new(),
+            protocol_versions,
+            timings: Timings {
+                connect,
+                discover_outputs,
+                ..Timings::default()
+            },
+        })
+    }
+
+    /// Query the logical position, size and name of every output with xdg-output
+    fn xdg_outputs(
+        event_queue: &mut EventQueue,
+        xdg_output_manager: &Main<ZxdgOutputManagerV1>,
+        wayland_outputs: &[WaylandOutput],
+    ) -> Vec<WaylandOutput> {
         let mut final_wayland_outputs = Vec::new();
 
-        let xdg_output_manager = globals.instantiate_exact::<ZxdgOutputManagerV1>(3).context("Failed to create xdg output manger. Does your compositor implement ZxdgOutputManagerV1?")?;
-        for wayland_output in wayland_outputs.borrow().iter() {
+        for wayland_output in wayland_outputs {
             let xdg_output = xdg_output_manager.get_xdg_output(&wayland_output.raw);
 
             let output_name = Rc::new(RefCell::new(String::new()));
@@ -106,8 +260,14 @@ impl PlatformWayland {
                 let output_width = output_width.clone();
                 let output_height = output_height.clone();
 
-                move |_handle, event, _| {
+                move |handle, event, _| {
                     use wayland_protocols::unstable::xdg_output::v1::client::zxdg_output_v1::Event;
+                    trace!(
+                        target: OUTPUT_DISCOVERY_LOG_TARGET,
+                        "zxdg_output_v1@{}: {:?}",
+                        handle.as_ref().id(),
+                        event
+                    );
                     match event {
                         Event::LogicalPosition { x, y } => {
                             debug!("Xdg output logical position event");
@@ -133,6 +293,7 @@ impl PlatformWayland {
             event_queue
                 .sync_roundtrip(&mut (), |_, _, _| unreachable!())
                 .unwrap();
+            xdg_output.destroy();
 
             let wayland_output = WaylandOutput {
                 raw: wayland_output.raw.clone(),
@@ -143,26 +304,17 @@ impl PlatformWayland {
                     width: output_width.take(),
                     height: output_height.take(),
                     scale: wayland_output.output.scale,
+                    physical_width: wayland_output.output.physical_width,
+                    physical_height: wayland_output.output.physical_height,
                 },
             };
             info!("Found output: {:?}", wayland_output);
 
             final_wayland_outputs.push(wayland_output);
         }
+        xdg_output_manager.destroy();
 
-        // Instantiating screencopy manager
-        let screencopy_manager = globals
-            .instantiate_exact::<ZwlrScreencopyManagerV1>(3)
-            .context(
-            "Failed to create screencopy manager. Does your compositor implement ZwlrScreencopy?",
-        )?;
-
-        Ok(PlatformWayland {
-            event_queue,
-            globals,
-            screencopy_manager,
-            outputs: final_wayland_outputs,
-        })
+        final_wayland_outputs
     }
 
     fn find_wl_output(&self, output: &Output) -> Result<Main<WlOutput>> {
@@ -171,28 +323,52 @@ impl PlatformWayland {
                 return Ok(wayland_output.raw.clone());
             }
         }
-        bail!("No output found")
+        bail!(ScrcapError::NoOutput(format!(
+            "No Wayland output named {} found",
+            output.name
+        )))
     }
-}
 
-impl Platform for PlatformWayland {
-    fn outputs(&self) -> Vec<Output> {
-        self.outputs
-            .iter()
-            .map(|wayland_output| wayland_output.output.clone())
-            .collect::<Vec<_>>()
+    /// Capture a frame and convert it into a format that can be encoded if requested
+    fn capture(
+        &mut self,
+        output: &Output,
+        overlay_cursor: bool,
+        region: Option<Region>,
+        convert: bool,
+    ) -> Result<Frame> {
+        let start = Instant::now();
+        let copied_frame = self.copy_frame(output, overlay_cursor, region, false)?;
+        let copied = Instant::now();
+        let frame = read_frame(copied_frame, convert)?;
+        self.timings.capture += copied - start;
+        self.timings.convert += copied.elapsed();
+        Ok(frame)
     }
 
-    fn capture_frame(
+    /// Version of sway if its IPC socket can be reached. Asked for on the first call only.
+    fn sway_version(&self) -> Option<&swayipc::Version> {
+        self.sway_version.get_or_init(sway_version).as_ref()
+    }
+
+    /// Let the compositor copy a frame into a new shm file and wait until it is done. With
+    /// `with_damage` the compositor waits until the output changed and reports the damage.
+    fn copy_frame(
         &mut self,
         output: &Output,
         overlay_cursor: bool,
         region: Option<Region>,
-    ) -> anyhow::Result<Frame> {
+        with_damage: bool,
+    ) -> Result<CopiedFrame> {
         debug!("Taking screenshot of output {:?}", output.name);
         let wl_output_handle = self.find_wl_output(output)?;
 
-        let frame = if let Some(region) = region {
+        let frame = FrameGuard(if let Some(region) = region {
+            if region.area() == 0 {
+                bail!(ScrcapError::InvalidRegion(
+                    "Capture region has zero area".into()
+                ));
+            }
             debug!("Capture screenshot of region {:?}", region);
             self.screencopy_manager.capture_output_region(
                 overlay_cursor as i32,
@@ -206,30 +382,49 @@ impl Platform for PlatformWayland {
             debug!("Capture screenshot of whole screen");
             self.screencopy_manager
                 .capture_output(overlay_cursor as i32, &wl_output_handle)
-        };
+        });
 
         let frame_formats = Rc::new(RefCell::new(Vec::new()));
         let frame_state = Rc::new(RefCell::new(None));
         let frame_buffer_done = Rc::new(AtomicBool::new(false));
+        let frame_damage = Rc::new(RefCell::new(Vec::new()));
+        let frame_y_invert = Rc::new(AtomicBool::new(false));
+        // Before version 3 there is no buffer_done event and a single Buffer event is sent
+        let has_buffer_done_event = self.screencopy_manager.as_ref().version() >= 3;
 
         frame.quick_assign({
         let frame_formats = frame_formats.clone();
         let frame_state = frame_state.clone();
         let frame_buffer_done = frame_buffer_done.clone();
+        let frame_damage = frame_damage.clone();
+        let frame_y_invert = frame_y_invert.clone();
         move |_, event, _| {
-            use wayland_protocols::wlr::unstable::screencopy::v1::client::zwlr_screencopy_frame_v1::Event;
+            use wayland_protocols::wlr::unstable::screencopy::v1::client::zwlr_screencopy_frame_v1::{Event, Flags};
             match event {
                 Event::Buffer { format, width, height, stride } =>  {
                     debug!("Received Buffer event");
+                    trace!(
+                        target: STRIDE_LOG_TARGET,
+                        "{:?} buffer of {}x{}: advertised stride {}, expected stride {}",
+                        format,
+                        width,
+                        height,
+                        stride,
+                        width * 4
+                    );
                     frame_formats.borrow_mut().push(FrameDescription {
                         format: format.into(),
                         width,
                         height,
                         stride,
-                    })
+                    });
+                    if !has_buffer_done_event {
+                        frame_buffer_done.store(true, Ordering::SeqCst);
+                    }
                 },
-                Event::Flags { flags: _ } => {
-                    debug!("Received Flags event");
+                Event::Flags { flags } => {
+                    debug!("Received Flags event: {:?}", flags);
+                    frame_y_invert.store(flags.contains(Flags::YInvert), Ordering::SeqCst);
                 },
                 Event::Ready { tv_sec_hi: _, tv_sec_lo: _, tv_nsec: _ } => {
                     // On succesfully copy, a Ready event is sent. Otherwise, a
@@ -242,8 +437,9 @@ impl Platform for PlatformWayland {
                     debug!("Received Failed event");
                     frame_state.borrow_mut().replace(FrameState::Failed);
                 },
-                Event::Damage { x: _, y: _, width: _, height: _ } => {
+                Event::Damage { x, y, width, height } => {
                     debug!("Received Damage event");
+                    frame_damage.borrow_mut().push(Region::new(x as i32, y as i32, width as i32, height as i32));
                 },
                 Event::LinuxDmabuf { format: _, width: _, height: _ } => {
                     debug!("Received LinuxDmabuf event");
@@ -265,6 +461,15 @@ impl Platform for PlatformWayland {
         while !frame_buffer_done.load(Ordering::SeqCst) {
             self.event_queue
                 .dispatch(&mut (), |_, _, _| unreachable!())?;
+
+            // The protocol gives no reason for a failure. A compositor that refuses the capture
+            // altogether, e.g. while the session is locked, fails before offering any buffer.
+            if frame_state.borrow_mut().take() == Some(FrameState::Failed) {
+                error!("Compositor refused to capture the frame");
+                bail!(ScrcapError::CaptureFailed(
+                    "Capture blocked, possibly by a lock screen or security policy".into()
+                ));
+            }
         }
 
         debug!(
@@ -272,19 +477,21 @@ impl Platform for PlatformWayland {
             frame_formats
         );
 
-        // Filter advertised formats and select the first one that matches.
+        // Take the required format or filter advertised formats and select the first one that
+        // matches.
         let frame_format = frame_formats
             .borrow()
             .iter()
-            .find(|frame| {
-                matches!(
+            .find(|frame| match self.required_format {
+                Some(required_format) => frame.format == required_format,
+                None => matches!(
                     frame.format,
                     FrameFormat::Xbgr2101010
                         | FrameFormat::Abgr2101010
                         | FrameFormat::Argb8888
                         | FrameFormat::Xrgb8888
                         | FrameFormat::Xbgr8888
-                )
+                ),
             })
             .copied();
         debug!("Selected frame buffer format: {:?}", frame_format);
@@ -293,20 +500,46 @@ impl Platform for PlatformWayland {
         let frame_format = match frame_format {
             Some(format) => format,
             None => {
-                bail!("No suitable frame format found");
+                let advertised_formats = frame_formats
+                    .borrow()
+                    .iter()
+                    .map(|frame| frame.format)
+                    .collect::<Vec<_>>();
+                match self.required_format {
+                    Some(required_format) => bail!(ScrcapError::CaptureFailed(format!(
+                        "Compositor does not offer frame format {:?}, it offers {:?}",
+                        required_format, advertised_formats
+                    ))),
+                    None => bail!(ScrcapError::CaptureFailed(format!(
+                        "No suitable frame format found in {:?}",
+                        advertised_formats
+                    ))),
+                }
             }
         };
 
         // Bytes of data in the frame = stride * height.
         let frame_bytes = frame_format.stride * frame_format.height;
+        if frame_bytes == 0 {
+            bail!(ScrcapError::InvalidRegion(
+                "Capture region has zero area".into()
+            ));
+        }
 
         // Create an in memory file and return it's file descriptor.
         let mem_fd = create_shm_fd()?;
         let mem_file = unsafe { File::from_raw_fd(mem_fd) };
-        mem_file.set_len(frame_bytes as u64)?;
+        mem_file
+            .set_len(frame_bytes as u64)
+            .map_err(|err| frame_memory_error(err, &frame_format))?;
 
         // Instantiate shm global.
-        let shm = self.globals.instantiate_exact::<wl_shm::WlShm>(1)?;
+        let shm = self
+            .globals
+            .instantiate_range::<wl_shm::WlShm>(1, WL_SHM_VERSION)
+            .context(ScrcapError::MissingProtocol(
+                "Compositor does not offer shared memory buffers (wl_shm)".into(),
+            ))?;
         let shm_pool = shm.create_pool(mem_fd, frame_bytes as i32);
         let buffer = shm_pool.create_buffer(
             0,
@@ -317,35 +550,415 @@ impl Platform for PlatformWayland {
         );
 
         // Copy the pixel data advertised by the compositor into the buffer we just created.
-        frame.copy(&buffer);
+        if with_damage {
+            frame.copy_with_damage(&buffer);
+        } else {
+            frame.copy(&buffer);
+        }
 
-        let frame = read_frame(&mut self.event_queue, frame_state, frame_format, &mem_file)?;
+        let copy_result = wait_for_copy(&mut self.event_queue, frame_state);
 
-        Ok(frame)
+        // The compositor is done with the frame, release the objects of this capture
+        drop(frame);
+        buffer.destroy();
+        shm_pool.destroy();
+        copy_result?;
+
+        Ok(CopiedFrame {
+            mem_file,
+            frame_format,
+            damage: frame_damage.take(),
+            y_invert: frame_y_invert.load(Ordering::SeqCst),
+        })
+    }
+}
+
+impl Platform for PlatformWayland {
+    fn outputs(&self) -> Vec<Output> {
+        self.outputs
+            .iter()
+            .map(|wayland_output| wayland_output.output.clone())
+            .collect::<Vec<_>>()
+    }
+
+    fn capture_frame(
+        &mut self,
+        output: &Output,
+        overlay_cursor: bool,
+        region: Option<Region>,
+    ) -> anyhow::Result<Frame> {
+        self.capture(output, overlay_cursor, region, true)
+    }
+
+    fn capture_frame_raw(
+        &mut self,
+        output: &Output,
+        overlay_cursor: bool,
+        region: Option<Region>,
+    ) -> Result<Frame> {
+        self.capture(output, overlay_cursor, region, false)
+    }
+
+    fn capture_frame_fd(
+        &mut self,
+        output: &Output,
+        overlay_cursor: bool,
+        region: Option<Region>,
+    ) -> Result<(RawFd, FrameDescription)> {
+        let copied_frame = self.copy_frame(output, overlay_cursor, region, false)?;
+        if copied_frame.y_invert {
+            let frame_format = copied_frame.frame_format;
+            let mut data = unsafe { MmapMut::map_mut(&copied_frame.mem_file) }
+                .map_err(|err| frame_memory_error(err, &frame_format))?;
+            flip_rows(&mut data, frame_format.stride, frame_format.height);
+        }
+        Ok((
+            copied_frame.mem_file.into_raw_fd(),
+            copied_frame.frame_format,
+        ))
+    }
+
+    fn capture_frame_with_damage(
+        &mut self,
+        output: &Output,
+        overlay_cursor: bool,
+        region: Option<Region>,
+    ) -> Result<Frame> {
+        // copy_with_damage was added in version 2
+        if self.screencopy_manager.as_ref().version() < 2 {
+            bail!(ScrcapError::MissingProtocol(
+                "Capturing damage requires screencopy manager version 2".into()
+            ));
+        }
+        let copied_frame = self.copy_frame(output, overlay_cursor, region, true)?;
+        read_frame(copied_frame, true)
+    }
+
+    fn session_info(&self) -> SessionInfo {
+        session_info(self.sway_version())
+    }
+
+    fn version_info(&self) -> VersionInfo {
+        VersionInfo {
+            swayipc: self.sway_version().is_some(),
+            ..self.protocol_versions
+        }
+    }
+
+    fn timings(&self) -> Timings {
+        self.timings
+    }
+
+    fn set_required_format(&mut self, format: Option<FrameFormat>) {
+        self.required_format = format;
+    }
+
+    fn focused_output(&self) -> Result<Output> {
+        // Avoid the retries of connecting on other compositors
+        if env::var_os("SWAYSOCK").is_none() {
+            bail!("SWAYSOCK is not set, is sway running?");
+        }
+        let mut connection = connect_sway()?;
+        let sway_outputs = connection.get_outputs()?;
+        let Some(focused) = sway_outputs.iter().find(|output| output.focused) else {
+            bail!("Sway reports no focused output");
+        };
+
+        self.outputs
+            .iter()
+            .map(|wayland_output| &wayland_output.output)
+            .find(|output| output.name == focused.name)
+            .cloned()
+            .ok_or_else(|| {
+                ScrcapError::NoOutput(format!("No Wayland output named {} found", focused.name))
+                    .into()
+            })
     }
 
     fn focused_window_area(&self) -> Result<Region> {
-        let mut connection = swayipc::Connection::new()?;
+        let mut connection = connect_sway()?;
         let tree = connection.get_tree()?;
         let focused_node = tree.find_focused_as_ref(|node: _| node.focused);
         if let Some(focused_node) = focused_node {
-            let rect = &focused_node.rect;
-            let window_rect = &focused_node.window_rect;
+            let region = window_region(focused_node);
+            debug!("Focused window: {:?} {:?}", focused_node.name, region);
+            return Ok(region);
+        }
+
+        bail!("Could not find an active window")
+    }
+
+    fn focused_window_name(&self) -> Result<String> {
+        let mut connection = connect_sway()?;
+        let tree = connection.get_tree()?;
+        let Some(focused_node) = tree.find_focused_as_ref(|node: _| node.focused) else {
+            bail!("Could not find an active window");
+        };
+
+        let class = focused_node
+            .window_properties
+            .as_ref()
+            .and_then(|properties| properties.class.clone());
+        focused_node
+            .app_id
+            .clone()
+            .or(class)
+            .or_else(|| focused_node.name.clone())
+            .ok_or_else(|| anyhow!("The active window has no app id or title"))
+    }
+
+    fn wait_for_window(&self, name: &str, timeout: Duration) -> Result<Region> {
+        let mut connection = connect_sway()?;
+        let start = Instant::now();
+        loop {
+            let tree = connection.get_tree()?;
+            if let Some(node) = tree.find_as_ref(|node| window_matches(node, name)) {
+                let region = window_region(node);
+                debug!("Found window: {:?} {:?}", node.name, region);
+                return Ok(region);
+            }
+
+            if start.elapsed() >= timeout {
+                bail!(
+                    "Timed out after {}s waiting for a window matching {:?}",
+                    timeout.as_secs_f32(),
+                    name
+                );
+            }
+            thread::sleep(WINDOW_POLL_INTERVAL);
+        }
+    }
 
-            let x = rect.x + window_rect.x;
-            let y = rect.y + window_rect.y;
-            let width = window_rect.width;
-            let height = window_rect.height;
+    fn marked_window_area(&self, mark: &str) -> Result<Region> {
+        let mut connection = connect_sway()?;
+        let tree = connection.get_tree()?;
+        let Some(node) = tree.find_as_ref(|node| node.marks.iter().any(|m| m == mark)) else {
+            bail!("No window has the mark {:?}", mark);
+        };
+        if node.visible == Some(false) {
+            bail!(
+                "The window with the mark {:?} is not visible, it may be hidden in the scratchpad",
+                mark
+            );
+        }
+
+        let region = window_region(node);
+        debug!("Marked window: {:?} {:?}", node.name, region);
+        Ok(region)
+    }
 
-            debug!(
-                "Focused window: {:?} x:{}, y: {}, width: {}, height: {}",
-                focused_node.name, x, y, width, height
+    fn workspace_area(&self, name: &str) -> Result<Region> {
+        let mut connection = connect_sway()?;
+        let workspaces = connection.get_workspaces()?;
+        let Some(workspace) = workspaces
+            .iter()
+            .find(|workspace| workspace.name == name || workspace.num.to_string() == name)
+        else {
+            bail!("No workspace named {} found", name);
+        };
+        if !workspace.visible {
+            bail!(
+                "Workspace {} is not visible, it is hidden on output {}",
+                name,
+                workspace.output
             );
+        }
+
+        let rect = &workspace.rect;
+        let region = Region::new(rect.x, rect.y, rect.width, rect.height);
+        debug!("Workspace {}: {:?}", workspace.name, region);
+        Ok(region)
+    }
+}
+
+/// Write a report about the compositor and the protocols and outputs it offers. Failures are
+/// part of the report, so it is complete even if capturing does not work.
+pub fn diagnose(report: &mut dyn Write) -> Result<()> {
+    writeln!(
+        report,
+        "Desktop: {}",
+        env::var("XDG_CURRENT_DESKTOP").unwrap_or_else(|_| "unknown".into())
+    )?;
 
-            return Ok(Region::new(x, y, width, height));
+    match connect_sway().and_then(|mut connection| Ok(connection.get_version()?)) {
+        Ok(version) => writeln!(report, "Sway IPC: {}", version.human_readable)?,
+        Err(err) => writeln!(report, "Sway IPC: {:#}", err)?,
+    }
+
+    let display = match Display::connect_to_env() {
+        Ok(display) => display,
+        Err(err) => {
+            writeln!(
+                report,
+                "Wayland: Could not connect to Wayland server: {}",
+                err
+            )?;
+            return Ok(());
         }
+    };
+    let mut event_queue = display.create_event_queue();
+    let attached_display = (*display).clone().attach(event_queue.token());
+    let globals = GlobalManager::new(&attached_display);
+    event_queue.sync_roundtrip(&mut (), |_, _, _| ())?;
 
-        bail!("Could not find an active window")
+    let global_list = globals.list();
+    for (interface, required) in [
+        (wl_shm::WlShm::NAME, 1),
+        (WlOutput::NAME, 1),
+        (ZxdgOutputManagerV1::NAME, XDG_OUTPUT_MANAGER_MIN_VERSION),
+        (ZwlrScreencopyManagerV1::NAME, 1),
+    ] {
+        match global_list.iter().find(|(_, name, _)| name == interface) {
+            Some((_, _, version)) if *version >= required => {
+                writeln!(report, "{}: version {}", interface, version)?
+            }
+            Some((_, _, version)) => writeln!(
+                report,
+                "{}: version {}, but version {} is required",
+                interface, version, required
+            )?,
+            None => writeln!(report, "{}: missing", interface)?,
+        }
+    }
+
+    match PlatformWayland::new() {
+        Ok(platform) => {
+            for output in platform.outputs() {
+                writeln!(
+                    report,
+                    "Output {}: {}x{} at {},{}, scale {}",
+                    output.name, output.width, output.height, output.x, output.y, output.scale
+                )?;
+            }
+        }
+        Err(err) => writeln!(report, "Outputs: {:#}", err)?,
+    }
+
+    Ok(())
+}
+
+/// Version of sway if its IPC socket can be reached
+fn sway_version() -> Option<swayipc::Version> {
+    env::var_os("SWAYSOCK").and_then(|_| {
+        swayipc::Connection::new()
+            .and_then(|mut connection| connection.get_version())
+            .map_err(|err| debug!("Could not get the version of sway: {}", err))
+            .ok()
+    })
+}
+
+/// Version of an interface that instantiating it with the given range binds, `None` if the
+/// compositor does not offer a version in the range
+fn negotiated_version(globals: &GlobalManager, interface: &str, min: u32, max: u32) -> Option<u32> {
+    globals
+        .list()
+        .into_iter()
+        .find(|(_, name, _)| name == interface)
+        .map(|(_, _, version)| version.min(max))
+        .filter(|&version| version >= min)
+}
+
+/// Find out which compositor and Wayland socket scrcap is connected to. Only sway tells its
+/// version, other compositors are named after XDG_CURRENT_DESKTOP.
+fn session_info(sway_version: Option<&swayipc::Version>) -> SessionInfo {
+    let compositor = match sway_version {
+        Some(version) => Some(format!("sway {}", version.human_readable)),
+        None => env::var("XDG_CURRENT_DESKTOP")
+            .ok()
+            .filter(|desktop| !desktop.is_empty()),
+    };
+    // The same default as the Wayland client library
+    let wayland_socket = env::var("WAYLAND_DISPLAY").unwrap_or_else(|_| "wayland-0".into());
+
+    let session_info = SessionInfo {
+        compositor,
+        wayland_socket: Some(wayland_socket),
+    };
+    debug!("Connected to {:?}", session_info);
+    session_info
+}
+
+/// Connect to the sway IPC socket. Right after login the socket may not be ready yet, so
+/// connecting gets retried a few times.
+fn connect_sway() -> Result<swayipc::Connection> {
+    let mut attempt = 1;
+    loop {
+        match swayipc::Connection::new() {
+            Ok(connection) => return Ok(connection),
+            Err(err) if attempt < SWAY_CONNECT_ATTEMPTS => {
+                debug!("Connecting to sway failed (attempt {}): {}", attempt, err);
+                thread::sleep(SWAY_CONNECT_RETRY_DELAY * attempt);
+                attempt += 1;
+            }
+            Err(err) => {
+                return Err(err).context(
+                    "Could not connect to sway. Is sway running and SWAYSOCK set correctly?",
+                )
+            }
+        }
+    }
+}
+
+/// Area of the window content in global coordinates
+fn window_region(node: &swayipc::Node) -> Region {
+    let rect = &node.rect;
+    let window_rect = &node.window_rect;
+
+    Region::new(
+        rect.x + window_rect.x,
+        rect.y + window_rect.y,
+        window_rect.width,
+        window_rect.height,
+    )
+}
+
+/// Check if the node is a window with the given app_id or a title containing name
+fn window_matches(node: &swayipc::Node, name: &str) -> bool {
+    if node.pid.is_none() {
+        return false;
+    }
+
+    let app_id_matches = node.app_id.as_deref() == Some(name);
+    let title_matches = node
+        .name
+        .as_deref()
+        .is_some_and(|title| title.contains(name));
+    app_id_matches || title_matches
+}
+
+/// Screencopy frame that gets destroyed when it goes out of scope, so that a capture failing
+/// half way does not leave it behind on the connection
+struct FrameGuard(Main<ZwlrScreencopyFrameV1>);
+
+impl Deref for FrameGuard {
+    type Target = Main<ZwlrScreencopyFrameV1>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Drop for FrameGuard {
+    fn drop(&mut self) {
+        self.0.destroy();
+    }
+}
+
+impl Drop for PlatformWayland {
+    fn drop(&mut self) {
+        // Release the compositor objects, so that long running processes creating platforms
+        // repeatedly do not accumulate them
+        self.screencopy_manager.destroy();
+        for wayland_output in &self.outputs {
+            // wl_output.release was added in version 3
+            if wayland_output.raw.as_ref().version() >= 3 {
+                wayland_output.raw.release();
+            }
+        }
+
+        if let Err(err) = self.event_queue.display().flush() {
+            warn!("Could not flush Wayland connection: {}", err);
+        }
     }
 }
 
@@ -355,6 +968,26 @@ struct WaylandOutput {
     output: Output,
 }
 
+/// Find the output belonging to the Wayland handle
+fn find_output<'a>(
+    wayland_outputs: &'a mut [WaylandOutput],
+    handle: &Main<WlOutput>,
+) -> Option<&'a mut Output> {
+    wayland_outputs
+        .iter_mut()
+        .find(|wayland_output| wayland_output.raw.as_ref().equals(handle.as_ref()))
+        .map(|wayland_output| &mut wayland_output.output)
+}
+
+/// Frame data copied by the compositor into a shm file
+struct CopiedFrame {
+    mem_file: File,
+    frame_format: FrameDescription,
+    damage: Vec<Region>,
+    /// The rows are stored bottom to top
+    y_invert: bool,
+}
+
 /// State of the frame after attemting to copy it's data to a wl_buffer.
 #[derive(Debug, Copy, Clone, PartialEq)]
 enum FrameState {
@@ -391,63 +1024,65 @@ impl Into<wl_shm::Format> for FrameFormat {
     }
 }
 
-#[derive(Error, Debug)]
-enum ReadFrameError {
-    #[error("Could not copy frame from compositor to client")]
-    FrameCopy,
-}
-
-fn read_frame(
+/// Dispatch frame events until the compositor finished or failed copying the frame
+fn wait_for_copy(
     event_queue: &mut wayland_client::EventQueue,
     frame_state: Rc<RefCell<Option<FrameState>>>,
-    frame_format: FrameDescription,
-    mem_file: &File,
-) -> Result<Frame> {
+) -> Result<()> {
     loop {
         // Let the compositor dispatch Frame events
         debug!("Dispatch event queue and wait for Failed or Finished events");
         event_queue.dispatch(&mut (), |_, _, _| {})?;
 
-        // Try to read the frame from the compositor
-        let frame_copy = try_read_frame(frame_state.clone(), frame_format, &mem_file)?;
-        if frame_copy.is_some() {
-            debug!("Read frame succesful");
-            // Compositor did not emit Finished or Failed events. Let's try again.
-            return Ok(frame_copy.unwrap());
+        if let Some(state) = frame_state.borrow_mut().take() {
+            match state {
+                FrameState::Failed => {
+                    error!("Frame copy failed");
+                    bail!(ScrcapError::CaptureFailed(
+                        "Could not copy frame from compositor to client".into()
+                    ));
+                }
+                FrameState::Finished => {
+                    debug!("Frame copy succesful");
+                    return Ok(());
+                }
+            }
         }
+        // Compositor did not emit Finished or Failed events. Let's try again.
         debug!("Failed or Finished events did not arrive yet. Try again.");
     }
 }
 
-fn try_read_frame(
-    frame_state: Rc<RefCell<Option<FrameState>>>,
-    frame_format: FrameDescription,
-    mem_file: &File,
-) -> Result<Option<Frame>> {
-    // Basically reads, if frame state is not None then...
-    if let Some(state) = frame_state.borrow_mut().take() {
-        let frame_copy = match state {
-            FrameState::Failed => {
-                error!("Frame copy failed");
-                bail!(ReadFrameError::FrameCopy);
-            }
-            FrameState::Finished => {
-                // Create a writeable memory map backed by a mem_file.
-                let mut frame_mmap = unsafe { MmapMut::map_mut(mem_file)? };
-                let data = &mut *frame_mmap;
-                let converter = create_converter(frame_format.format);
-                let frame_color_type = converter.convert_inplace(data);
-                Frame {
-                    frame_format,
-                    frame_color_type,
-                    frame_mmap,
-                }
-            }
-        };
-        return Ok(Some(frame_copy));
+/// Map the copied frame into memory and convert it if requested
+fn read_frame(copied_frame: CopiedFrame, convert: bool) -> Result<Frame> {
+    let frame_format = copied_frame.frame_format;
+    // Create a writeable memory map backed by a mem_file.
+    let mut frame_mmap = unsafe { MmapMut::map_mut(&copied_frame.mem_file) }
+        .map_err(|err| frame_memory_error(err, &frame_format))?;
+    if copied_frame.y_invert {
+        debug!("Flip y-inverted frame");
+        flip_rows(&mut frame_mmap, frame_format.stride, frame_format.height);
     }
+    // Raw frames keep the compositor's pixel layout. All supported formats use
+    // four bytes per pixel.
+    let frame_color_type = if convert {
+        let converter = create_converter(frame_format.format);
+        convert_rows_inplace(
+            converter.as_ref(),
+            &mut frame_mmap,
+            frame_format.width,
+            frame_format.stride,
+        )
+    } else {
+        ColorType::Rgba8
+    };
 
-    Ok(None)
+    Ok(Frame {
+        frame_format,
+        frame_color_type,
+        frame_mmap,
+        damage: copied_frame.damage,
+    })
 }
 
 /// Return a RawFd to a shm file. We use memfd create on linux and shm_open for BSD support.
@@ -459,7 +1094,7 @@ fn create_shm_fd() -> std::io::Result<RawFd> {
     loop {
         // Create a file that closes on succesful execution and seal it's operations.
         match memfd::memfd_create(
-            CStr::from_bytes_with_nul(b"wayshot\0").unwrap(),
+            &CString::new(SHM_NAME_PREFIX).unwrap(),
             memfd::MemFdCreateFlag::MFD_CLOEXEC | memfd::MemFdCreateFlag::MFD_ALLOW_SEALING,
         ) {
             Ok(fd) => {
@@ -481,11 +1116,17 @@ fn create_shm_fd() -> std::io::Result<RawFd> {
     }
 
     // Fallback to using shm_open.
-    let sys_time = SystemTime::now();
-    let mut mem_file_handle = format!(
-        "/wayshot-{}",
-        sys_time.duration_since(UNIX_EPOCH).unwrap().subsec_nanos()
-    );
+    let shm_name = || {
+        format!(
+            "/{}-{}",
+            SHM_NAME_PREFIX,
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .subsec_nanos()
+        )
+    };
+    let mut mem_file_handle = shm_name();
     loop {
         match mman::shm_open(
             // O_CREAT = Create file if does not exist.
@@ -510,10 +1151,7 @@ fn create_shm_fd() -> std::io::Result<RawFd> {
             },
             Err(nix::errno::Errno::EEXIST) => {
                 // If a file with that handle exists then change the handle
-                mem_file_handle = format!(
-                    "/wayshot-{}",
-                    sys_time.duration_since(UNIX_EPOCH).unwrap().subsec_nanos()
-                );
+                mem_file_handle = shm_name();
                 continue;
             }
             Err(nix::errno::Errno::EINTR) => continue,
@@ -0,0 +1,1305 @@
+mod common;
+
+use chrono::{NaiveDate, NaiveTime};
+use clap::Parser;
+use common::{output, MockPlatform};
+use scrcap::cli::{
+    self, default_output, duration_until, find_fifo, find_output_from_region, get_output,
+    get_region_from_args, get_window_region_from_args, parse_duration, pick_output,
+    run_region_stream, sanitize_filename, split_filename_extension, write_presets, CmdArgs,
+    LastCapture, OutputFilter, SessionStats,
+};
+use scrcap::config::Config;
+use scrcap::error::{exit_code, ScrcapError, EXIT_FAILURE};
+use scrcap::output::EncodingFormat;
+use scrcap::platform::{FrameFormat, Output, Region};
+use std::time::Duration;
+
+fn dual_outputs() -> Vec<Output> {
+    vec![
+        output("DP-1", 0, 0, 1920, 1080),
+        output("DP-2", 1920, 0, 2560, 1440),
+    ]
+}
+
+fn args(args: &[&str]) -> CmdArgs {
+    CmdArgs::parse_from(std::iter::once("scrcap").chain(args.iter().copied()))
+}
+
+#[test]
+fn get_output_defaults_to_first_output() {
+    let outputs = dual_outputs();
+    let output = get_output(None, &outputs).unwrap();
+    assert_eq!(output.name, "DP-1");
+}
+
+#[test]
+fn get_output_finds_output_by_name() {
+    let outputs = dual_outputs();
+    let output = get_output(Some("DP-2".into()), &outputs).unwrap();
+    assert_eq!(output.name, "DP-2");
+}
+
+#[test]
+fn get_output_fails_for_unknown_name() {
+    let outputs = dual_outputs();
+    assert!(get_output(Some("HDMI-A-1".into()), &outputs).is_err());
+}
+
+#[test]
+fn default_output_is_focused_output() {
+    let mut platform = MockPlatform::new(dual_outputs());
+    platform.focused_output = Some("DP-2".into());
+    let outputs = platform.outputs.clone();
+
+    assert_eq!(default_output(&platform, &outputs).unwrap().name, "DP-2");
+}
+
+#[test]
+fn default_output_falls_back_to_first_output() {
+    let platform = MockPlatform::new(dual_outputs());
+    let outputs = platform.outputs.clone();
+    assert_eq!(default_output(&platform, &outputs).unwrap().name, "DP-1");
+
+    assert!(default_output(&MockPlatform::new(Vec::new()), &[]).is_err());
+}
+
+#[test]
+fn run_captures_focused_output_by_default() {
+    let mut platform = MockPlatform::new(dual_outputs());
+    platform.focused_output = Some("DP-2".into());
+
+    cli::run(
+        &args(&["--preview-term"]),
+        &Config::default(),
+        &mut platform,
+    )
+    .unwrap();
+
+    assert_eq!(platform.captures, [("DP-2".to_string(), None)]);
+}
+
+#[test]
+fn get_output_fails_without_outputs() {
+    assert!(get_output(None, &[]).is_err());
+}
+
+#[test]
+fn pick_output_asks_until_valid_choice() {
+    let outputs = dual_outputs();
+    let mut prompt = Vec::new();
+    let output = pick_output(&outputs, "0\nfoo\n2\n".as_bytes(), &mut prompt).unwrap();
+
+    assert_eq!(output.name, "DP-2");
+    let prompt = String::from_utf8(prompt).unwrap();
+    assert!(prompt.contains("1: DP-1 (1920x1080 at 0,0)"));
+    assert_eq!(prompt.matches("Invalid choice").count(), 2);
+}
+
+#[test]
+fn pick_output_fails_without_input() {
+    let outputs = dual_outputs();
+    assert!(pick_output(&outputs, "".as_bytes(), Vec::new()).is_err());
+}
+
+#[test]
+fn region_is_none_without_region_args() {
+    let outputs = dual_outputs();
+    assert!(get_region_from_args(&args(&[]), &outputs[0]).is_none());
+}
+
+#[test]
+fn region_fills_remaining_output_area() {
+    let outputs = dual_outputs();
+    let region = get_region_from_args(&args(&["-x", "100", "-y", "80"]), &outputs[0])
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        (region.x, region.y, region.width, region.height),
+        (100, 80, 1820, 1000)
+    );
+}
+
+#[test]
+fn region_outside_output_is_invalid() {
+    let outputs = dual_outputs();
+    let region = get_region_from_args(
+        &args(&["-o", "DP-1", "-x", "1800", "-w", "200"]),
+        &outputs[0],
+    )
+    .unwrap();
+    assert!(region.is_err());
+}
+
+/// Output with a scale of 2 right of a 1920x1080 one
+fn scaled_output() -> Output {
+    Output {
+        scale: 2,
+        ..output("DP-2", 1920, 0, 1280, 720)
+    }
+}
+
+#[test]
+fn region_is_logical_by_default() {
+    let output = scaled_output();
+    let region = get_region_from_args(
+        &args(&[
+            "-o", "DP-2", "-x", "2020", "-y", "50", "-w", "200", "-H", "150",
+        ]),
+        &output,
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(region, Region::new(2020, 50, 200, 150));
+}
+
+#[test]
+fn physical_region_is_converted_to_logical() {
+    let output = scaled_output();
+    let region = get_region_from_args(
+        &args(&[
+            "--physical",
+            "-x",
+            "200",
+            "-y",
+            "100",
+            "-w",
+            "400",
+            "-H",
+            "300",
+        ]),
+        &output,
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(region, Region::new(2020, 50, 200, 150));
+
+    // Odd physical coordinates include the partially covered logical pixels
+    let region = get_region_from_args(&args(&["--physical", "-x", "1", "-w", "2"]), &output)
+        .unwrap()
+        .unwrap();
+    assert_eq!(region, Region::new(1920, 0, 2, 720));
+}
+
+#[test]
+fn physical_region_must_fit_on_output() {
+    let output = scaled_output();
+    assert!(
+        get_region_from_args(&args(&["--physical", "-x", "2000", "-w", "1000"]), &output)
+            .unwrap()
+            .is_err()
+    );
+    assert!(
+        get_region_from_args(&args(&["--physical", "-x", "2000", "-w", "560"]), &output)
+            .unwrap()
+            .is_ok()
+    );
+}
+
+#[test]
+fn aspect_derives_height_from_width() {
+    let outputs = dual_outputs();
+    let region = get_region_from_args(&args(&["-w", "1600", "--aspect", "16:9"]), &outputs[0])
+        .unwrap()
+        .unwrap();
+    assert_eq!(region, Region::new(0, 0, 1600, 900));
+}
+
+#[test]
+fn aspect_shrinks_region_to_fit() {
+    let outputs = dual_outputs();
+    let region = get_region_from_args(&args(&["--aspect", "4:3"]), &outputs[0])
+        .unwrap()
+        .unwrap();
+    assert_eq!(region, Region::new(0, 0, 1440, 1080));
+}
+
+#[test]
+fn aspect_must_be_positive() {
+    assert!(CmdArgs::try_parse_from(["scrcap", "--aspect", "16:0"]).is_err());
+    assert!(CmdArgs::try_parse_from(["scrcap", "--aspect", "16x9"]).is_err());
+}
+
+#[test]
+fn region_with_zero_area_is_invalid() {
+    let outputs = dual_outputs();
+    for region_args in [
+        &["-x", "1920", "-y", "10"][..],
+        &["-w", "0", "-H", "100"][..],
+        &["--physical", "-H", "0"][..],
+    ] {
+        let err = get_region_from_args(&args(region_args), &outputs[0])
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Capture region has zero area");
+        assert_eq!(exit_code(&err), 4);
+    }
+
+    let window = Region::new(0, 0, 640, 480);
+    let region = get_window_region_from_args(&args(&["-H", "0"]), window).unwrap();
+    assert!(region.is_err());
+}
+
+#[test]
+fn find_output_from_region_selects_containing_output() {
+    let outputs = dual_outputs();
+    let output = find_output_from_region(Region::new(2000, 100, 300, 300), &outputs).unwrap();
+    assert_eq!(output.name, "DP-2");
+}
+
+#[test]
+fn find_output_from_region_fails_outside_outputs() {
+    let outputs = dual_outputs();
+    let err = find_output_from_region(Region::new(5000, 0, 10, 10), &outputs).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Region 5000,0 10x10 is outside of all outputs. The nearest output DP-2 covers x 1920 to \
+         4480 and y 0 to 1440"
+    );
+}
+
+#[test]
+fn find_output_from_region_explains_spanning_regions() {
+    let outputs = dual_outputs();
+
+    let err = find_output_from_region(Region::new(1900, 0, 40, 10), &outputs).unwrap_err();
+    assert!(err.to_string().contains("spans the outputs DP-1, DP-2"));
+
+    let err = find_output_from_region(Region::new(0, 1000, 40, 100), &outputs).unwrap_err();
+    assert!(err.to_string().contains("exceeds output DP-1"));
+}
+
+#[test]
+fn run_captures_selected_output() {
+    let directory = std::env::temp_dir();
+    let mut platform = MockPlatform::new(dual_outputs());
+
+    cli::run(
+        &args(&[
+            "--output-name",
+            "DP-2",
+            "--directory",
+            directory.to_str().unwrap(),
+            "--filename",
+            "scrcap-test-run-output",
+        ]),
+        &Config::default(),
+        &mut platform,
+    )
+    .unwrap();
+
+    assert_eq!(platform.captures, vec![("DP-2".to_string(), None)]);
+    let path = directory.join("scrcap-test-run-output.png");
+    assert!(path.exists());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn run_uses_preset_of_given_config() {
+    let directory = std::env::temp_dir();
+    let mut platform = MockPlatform::new(dual_outputs());
+    let config = Config::parse("[presets]\ncorner = \"10,20 30x40\"").unwrap();
+
+    cli::run(
+        &args(&[
+            "--output-name",
+            "DP-2",
+            "--preset",
+            "corner",
+            "--directory",
+            directory.to_str().unwrap(),
+            "--filename",
+            "scrcap-test-run-preset",
+        ]),
+        &config,
+        &mut platform,
+    )
+    .unwrap();
+
+    assert_eq!(
+        platform.captures,
+        vec![("DP-2".to_string(), Some(Region::new(1930, 20, 30, 40)))]
+    );
+    let path = directory.join("scrcap-test-run-preset.png");
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn run_captures_active_window_on_its_output() {
+    let directory = std::env::temp_dir();
+    let mut platform = MockPlatform::new(dual_outputs());
+    let window = Region::new(2100, 200, 640, 480);
+    platform.focused_window = Some(window);
+
+    cli::run(
+        &args(&[
+            "--active",
+            "--directory",
+            directory.to_str().unwrap(),
+            "--filename",
+            "scrcap-test-run-active",
+            "--encoding-format",
+            "ppm",
+        ]),
+        &Config::default(),
+        &mut platform,
+    )
+    .unwrap();
+
+    assert_eq!(platform.captures.len(), 1);
+    let (name, region) = &platform.captures[0];
+    assert_eq!(name, "DP-2");
+    assert_eq!(region.unwrap().x, window.x);
+    let path = directory.join("scrcap-test-run-active.ppm");
+    assert!(path.exists());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn run_names_file_after_active_window() {
+    let directory = std::env::temp_dir().join("scrcap-test-name-from-window");
+    std::fs::create_dir_all(&directory).unwrap();
+    let mut platform = MockPlatform::new(dual_outputs());
+    platform.focused_window = Some(Region::new(100, 100, 64, 48));
+    platform.focused_window_name = Some("org.example/Viewer".into());
+
+    cli::run(
+        &args(&[
+            "--active",
+            "--name-from-window",
+            "--directory",
+            directory.to_str().unwrap(),
+        ]),
+        &Config::default(),
+        &mut platform,
+    )
+    .unwrap();
+
+    let filenames = std::fs::read_dir(&directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(filenames.len(), 1);
+    assert!(filenames[0].starts_with("screenshot-org.example_Viewer-"));
+    assert!(filenames[0].ends_with(".png"));
+}
+
+#[test]
+fn window_names_are_sanitized() {
+    assert_eq!(sanitize_filename("firefox"), "firefox");
+    assert_eq!(sanitize_filename("a/b\\c d\te"), "a_b_c_d_e");
+    assert_eq!(sanitize_filename("../secret"), "secret");
+    assert_eq!(sanitize_filename("  "), "window");
+    assert_eq!(sanitize_filename(&"x".repeat(100)).len(), 64);
+}
+
+#[test]
+fn name_from_window_requires_active() {
+    assert!(CmdArgs::try_parse_from(["scrcap", "--name-from-window"]).is_err());
+    assert!(
+        CmdArgs::try_parse_from(["scrcap", "--active", "--name-from-window", "-f", "shot"])
+            .is_err()
+    );
+}
+
+#[test]
+fn run_captures_marked_window() {
+    let directory = std::env::temp_dir();
+    let mut platform = MockPlatform::new(dual_outputs());
+    platform.marks = vec![("notes".into(), Region::new(2000, 100, 300, 200))];
+
+    cli::run(
+        &args(&[
+            "--mark",
+            "notes",
+            "-y",
+            "20",
+            "--directory",
+            directory.to_str().unwrap(),
+            "--filename",
+            "scrcap-test-run-mark",
+            "--encoding-format",
+            "ppm",
+        ]),
+        &Config::default(),
+        &mut platform,
+    )
+    .unwrap();
+
+    assert_eq!(
+        platform.captures,
+        vec![("DP-2".to_string(), Some(Region::new(2000, 120, 300, 180)))]
+    );
+    std::fs::remove_file(directory.join("scrcap-test-run-mark.ppm")).unwrap();
+
+    let err = cli::run(
+        &args(&["--mark", "unknown"]),
+        &Config::default(),
+        &mut platform,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("No window has the mark"));
+}
+
+#[test]
+fn run_composes_region_spanning_outputs() {
+    let directory = std::env::temp_dir();
+    let mut platform = MockPlatform::new(dual_outputs());
+
+    cli::run(
+        &args(&[
+            "-x",
+            "1800",
+            "-y",
+            "100",
+            "-w",
+            "400",
+            "-H",
+            "50",
+            "--directory",
+            directory.to_str().unwrap(),
+            "--filename",
+            "scrcap-test-run-spanning",
+        ]),
+        &Config::default(),
+        &mut platform,
+    )
+    .unwrap();
+
+    assert_eq!(
+        platform.captures,
+        vec![
+            ("DP-1".to_string(), Some(Region::new(1800, 100, 120, 50))),
+            ("DP-2".to_string(), Some(Region::new(1920, 100, 280, 50))),
+        ]
+    );
+    let path = directory.join("scrcap-test-run-spanning.png");
+    assert_eq!(image::image_dimensions(&path).unwrap(), (400, 50));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn window_region_is_offset_inside_window() {
+    let window = Region::new(2100, 200, 640, 480);
+    let region = get_window_region_from_args(&args(&["-x", "10", "-y", "30"]), window)
+        .unwrap()
+        .unwrap();
+    assert_eq!(region, Region::new(2110, 230, 630, 450));
+}
+
+#[test]
+fn window_region_exceeding_window_is_invalid() {
+    let window = Region::new(2100, 200, 640, 480);
+    let region = get_window_region_from_args(&args(&["-x", "10", "-w", "640"]), window).unwrap();
+    assert!(region.is_err());
+}
+
+#[test]
+fn last_capture_round_trips() {
+    let last_capture = LastCapture::from_args(&args(&[
+        "-o", "DP-2", "-x", "10", "--y=-20", "-w", "300", "-e", "jpg",
+    ]));
+    let parsed = LastCapture::parse(&toml::to_string(&last_capture).unwrap()).unwrap();
+    assert_eq!(parsed, last_capture);
+}
+
+#[test]
+fn only_captures_are_remembered() {
+    assert!(args(&["-o", "DP-2"]).is_capture());
+    assert!(!args(&["-o", "DP-2", "--list-presets"]).is_capture());
+    assert!(!args(&["--doctor"]).is_capture());
+    assert!(!args(&["--version-verbose"]).is_capture());
+}
+
+#[test]
+fn last_capture_is_overridden_by_explicit_args() {
+    let last_capture = LastCapture::from_args(&args(&["-o", "DP-2", "-x", "10", "-w", "300"]));
+    let repeated = LastCapture::from_args(&last_capture.apply(&args(&["-x", "50"])));
+    assert_eq!(repeated.output_name.as_deref(), Some("DP-2"));
+    assert_eq!(repeated.x, Some(50));
+    assert_eq!(repeated.width, Some(300));
+}
+
+#[test]
+fn run_executes_command_on_temporary_file() {
+    let marker = std::env::temp_dir().join("scrcap-test-exec-marker");
+    let mut platform = MockPlatform::new(dual_outputs());
+
+    cli::run(
+        &args(&[
+            "--filename",
+            "scrcap-test-exec",
+            "--exec",
+            &format!("cp {{}} {}", marker.display()),
+        ]),
+        &Config::default(),
+        &mut platform,
+    )
+    .unwrap();
+
+    assert!(marker.exists());
+    assert!(!std::env::temp_dir().join("scrcap-test-exec.png").exists());
+    std::fs::remove_file(marker).unwrap();
+}
+
+#[test]
+fn run_executes_command_on_file_in_given_directory() {
+    let directory = std::env::temp_dir().join("scrcap-test-exec-directory");
+    std::fs::create_dir_all(&directory).unwrap();
+    let mut platform = MockPlatform::new(dual_outputs());
+
+    cli::run(
+        &args(&[
+            "--filename",
+            "kept",
+            "--directory",
+            directory.to_str().unwrap(),
+            "--exec",
+            "test -f {}",
+            "--exec-keep",
+        ]),
+        &Config::default(),
+        &mut platform,
+    )
+    .unwrap();
+
+    let kept = directory.join("kept.png").exists();
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert!(kept);
+}
+
+#[test]
+fn run_captures_window_waited_for() {
+    let directory = std::env::temp_dir();
+    let mut platform = MockPlatform::new(dual_outputs());
+    let window = Region::new(100, 100, 320, 240);
+    platform.windows = vec![("Mozilla Firefox".into(), window)];
+
+    cli::run(
+        &args(&[
+            "--wait-for-window",
+            "Firefox",
+            "--directory",
+            directory.to_str().unwrap(),
+            "--filename",
+            "scrcap-test-run-wait",
+            "--encoding-format",
+            "ppm",
+        ]),
+        &Config::default(),
+        &mut platform,
+    )
+    .unwrap();
+
+    assert_eq!(platform.captures, vec![("DP-1".to_string(), Some(window))]);
+    let path = directory.join("scrcap-test-run-wait.ppm");
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn run_captures_workspace() {
+    let directory = std::env::temp_dir();
+    let mut platform = MockPlatform::new(dual_outputs());
+    let workspace = Region::new(1920, 30, 2560, 1410);
+    platform.workspaces = vec![("2".into(), workspace)];
+
+    cli::run(
+        &args(&[
+            "--workspace",
+            "2",
+            "--directory",
+            directory.to_str().unwrap(),
+            "--filename",
+            "scrcap-test-run-workspace",
+            "--encoding-format",
+            "ppm",
+        ]),
+        &Config::default(),
+        &mut platform,
+    )
+    .unwrap();
+
+    assert_eq!(
+        platform.captures,
+        vec![("DP-2".to_string(), Some(workspace))]
+    );
+    let path = directory.join("scrcap-test-run-workspace.ppm");
+    std::fs::remove_file(path).unwrap();
+
+    assert!(cli::run(
+        &args(&["--workspace", "3"]),
+        &Config::default(),
+        &mut platform
+    )
+    .is_err());
+}
+
+#[test]
+fn run_fails_without_required_format() {
+    let mut platform = MockPlatform::new(dual_outputs());
+
+    let result = cli::run(
+        &args(&["--require-format", "xbgr2101010", "--data-uri"]),
+        &Config::default(),
+        &mut platform,
+    );
+
+    assert_eq!(platform.required_format, Some(FrameFormat::Xbgr2101010));
+    assert!(result.is_err());
+    assert!(platform.captures.is_empty());
+}
+
+#[test]
+fn filename_extension_selects_format() {
+    assert_eq!(
+        split_filename_extension("shot.JPEG".into(), None),
+        ("shot".to_string(), EncodingFormat::Jpg)
+    );
+    assert_eq!(
+        split_filename_extension("shot.png".into(), Some(EncodingFormat::Png)),
+        ("shot".to_string(), EncodingFormat::Png)
+    );
+    assert_eq!(
+        split_filename_extension("shot.png".into(), Some(EncodingFormat::Png16)),
+        ("shot".to_string(), EncodingFormat::Png16)
+    );
+}
+
+#[test]
+fn filename_extension_is_kept_if_unknown_or_overridden() {
+    assert_eq!(
+        split_filename_extension("release.v1".into(), None),
+        ("release.v1".to_string(), EncodingFormat::Png)
+    );
+    assert_eq!(
+        split_filename_extension("shot.png".into(), Some(EncodingFormat::Ppm)),
+        ("shot.png".to_string(), EncodingFormat::Ppm)
+    );
+    assert_eq!(
+        split_filename_extension(".png".into(), None),
+        (".png".to_string(), EncodingFormat::Png)
+    );
+}
+
+#[test]
+fn run_saves_raw_frame_with_description() {
+    let directory = std::env::temp_dir();
+    let mut platform = MockPlatform::new(dual_outputs());
+
+    cli::run(
+        &args(&[
+            "--raw",
+            "-x",
+            "10",
+            "-y",
+            "10",
+            "-w",
+            "20",
+            "-H",
+            "5",
+            "--directory",
+            directory.to_str().unwrap(),
+            "--filename",
+            "scrcap-test-run-raw",
+        ]),
+        &Config::default(),
+        &mut platform,
+    )
+    .unwrap();
+
+    let raw_path = directory.join("scrcap-test-run-raw.raw");
+    let description_path = directory.join("scrcap-test-run-raw.json");
+    assert_eq!(std::fs::metadata(&raw_path).unwrap().len(), 20 * 4 * 5);
+    let description: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&description_path).unwrap()).unwrap();
+    assert_eq!(description["stride"], 80);
+    assert_eq!(description["format"], "Abgr8888");
+    std::fs::remove_file(raw_path).unwrap();
+    std::fs::remove_file(description_path).unwrap();
+}
+
+#[test]
+fn run_captures_each_listed_output() {
+    let directory = std::env::temp_dir();
+    let mut outputs = dual_outputs();
+    outputs.push(output("HDMI-A-1", 4480, 0, 1920, 1080));
+    let mut platform = MockPlatform::new(outputs);
+
+    cli::run(
+        &args(&[
+            "--output-name",
+            "DP-1,HDMI-A-1",
+            "--directory",
+            directory.to_str().unwrap(),
+            "--filename",
+            "scrcap-test-run-outputs.ppm",
+        ]),
+        &Config::default(),
+        &mut platform,
+    )
+    .unwrap();
+
+    assert_eq!(
+        platform.captures,
+        vec![("DP-1".to_string(), None), ("HDMI-A-1".to_string(), None)]
+    );
+    for output_name in ["DP-1", "HDMI-A-1"] {
+        let path = directory.join(format!("scrcap-test-run-outputs-{}.ppm", output_name));
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn run_reports_all_invalid_output_names() {
+    let mut platform = MockPlatform::new(dual_outputs());
+
+    let err = cli::run(
+        &args(&["-o", "DP-1,DP-3,DP-4"]),
+        &Config::default(),
+        &mut platform,
+    )
+    .unwrap_err();
+
+    assert!(err.to_string().contains("DP-3, DP-4"));
+    assert!(platform.captures.is_empty());
+}
+
+#[test]
+fn durations_are_parsed_with_units() {
+    assert_eq!(parse_duration("90s").unwrap(), Duration::from_secs(90));
+    assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+    assert!(parse_duration("30").is_err());
+    assert!(parse_duration("5d").is_err());
+    assert!(parse_duration("").is_err());
+}
+
+#[test]
+fn scheduled_time_is_today_or_tomorrow() {
+    let now = NaiveDate::from_ymd_opt(2023, 1, 10)
+        .unwrap()
+        .and_hms_opt(12, 0, 0)
+        .unwrap();
+
+    let later = NaiveTime::from_hms_opt(12, 30, 0).unwrap();
+    assert_eq!(duration_until(later, now), Duration::from_secs(30 * 60));
+
+    let earlier = NaiveTime::from_hms_opt(11, 0, 0).unwrap();
+    assert_eq!(duration_until(earlier, now), Duration::from_secs(23 * 3600));
+}
+
+#[test]
+fn presets_are_listed_in_columns() {
+    let config = Config::parse(
+        r#"
+[presets]
+top = "0,0 1920x40"
+left_half = "0,0 960x1080"
+huge = "0,0 3840x2160"
+"#,
+    )
+    .unwrap();
+
+    let mut report = Vec::new();
+    write_presets(&config, &dual_outputs()[1], &mut report).unwrap();
+    let report = String::from_utf8(report).unwrap();
+    let lines = report.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "NAME       GEOMETRY       OUTPUT  REGION");
+    assert!(lines[1].starts_with("huge       0,0 3840x2160  DP-2    invalid: "));
+    assert_eq!(
+        lines[2],
+        "left_half  0,0 960x1080   DP-2    1920,0 960x1080"
+    );
+    assert_eq!(lines[3], "top        0,0 1920x40    DP-2    1920,0 1920x40");
+}
+
+#[test]
+fn no_presets_are_reported() {
+    let mut report = Vec::new();
+    write_presets(&Config::default(), &dual_outputs()[0], &mut report).unwrap();
+    assert_eq!(
+        String::from_utf8(report).unwrap(),
+        "No presets configured\n"
+    );
+}
+
+fn mixed_dpi_outputs() -> Vec<Output> {
+    vec![
+        output("DP-1", 0, 0, 1920, 1080),
+        Output {
+            scale: 2,
+            ..output("DP-2", 1920, 0, 1920, 1080)
+        },
+        Output {
+            scale: 2,
+            ..output("DP-3", 3840, 0, 1280, 720)
+        },
+    ]
+}
+
+#[test]
+fn output_filter_compares_properties() {
+    let outputs = mixed_dpi_outputs();
+    let matching = |filter: &str| {
+        let filter = filter.parse::<OutputFilter>().unwrap();
+        outputs
+            .iter()
+            .filter(|output| filter.matches(output))
+            .map(|output| output.name.as_str())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(matching("scale>=2"), ["DP-2", "DP-3"]);
+    assert_eq!(matching("width >= 1920"), ["DP-1", "DP-2"]);
+    assert_eq!(matching("x<1920"), ["DP-1"]);
+    assert_eq!(matching("name!=DP-2"), ["DP-1", "DP-3"]);
+    assert_eq!(matching("name=DP-3"), ["DP-3"]);
+    assert_eq!(matching("height==720"), ["DP-3"]);
+}
+
+#[test]
+fn invalid_output_filters_are_rejected() {
+    for filter in ["scale", "depth>2", "scale>=two", "name>DP-1", "width~3"] {
+        assert!(filter.parse::<OutputFilter>().is_err(), "{}", filter);
+    }
+}
+
+#[test]
+fn run_captures_outputs_matching_filter() {
+    let directory = std::env::temp_dir();
+    let mut platform = MockPlatform::new(mixed_dpi_outputs());
+
+    cli::run(
+        &args(&[
+            "--outputs-where",
+            "scale>=2",
+            "--directory",
+            directory.to_str().unwrap(),
+            "--filename",
+            "scrcap-test-run-where",
+            "--encoding-format",
+            "ppm",
+        ]),
+        &Config::default(),
+        &mut platform,
+    )
+    .unwrap();
+
+    assert_eq!(
+        platform.captures,
+        vec![("DP-2".to_string(), None), ("DP-3".to_string(), None)]
+    );
+    for output_name in ["DP-2", "DP-3"] {
+        let path = directory.join(format!("scrcap-test-run-where-{}.ppm", output_name));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    let err = cli::run(
+        &args(&["--outputs-where", "scale>2"]),
+        &Config::default(),
+        &mut platform,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("No output matches"));
+}
+
+#[test]
+fn failures_have_distinct_exit_codes() {
+    let mut platform = MockPlatform::new(dual_outputs());
+
+    let err = cli::run(&args(&["-o", "HDMI-1"]), &Config::default(), &mut platform).unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<ScrcapError>(),
+        Some(ScrcapError::NoOutput(_))
+    ));
+    assert_eq!(exit_code(&err), 3);
+
+    let err = cli::run(
+        &args(&["-o", "DP-1", "-x", "1800", "-w", "200"]),
+        &Config::default(),
+        &mut platform,
+    )
+    .unwrap_err();
+    assert_eq!(exit_code(&err), 4);
+
+    let err = cli::run(
+        &args(&["--directory", "/nonexistent/scrcap", "-f", "shot"]),
+        &Config::default(),
+        &mut platform,
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<ScrcapError>(),
+        Some(ScrcapError::WriteFailed(_))
+    ));
+    assert_eq!(exit_code(&err), 7);
+
+    assert_eq!(exit_code(&anyhow::anyhow!("Other failure")), EXIT_FAILURE);
+}
+
+#[test]
+fn find_fifo_ignores_regular_files() {
+    let directory = std::env::temp_dir().join("scrcap-test-find-fifo");
+    std::fs::create_dir_all(&directory).unwrap();
+    nix::unistd::mkfifo(&directory.join("pipe"), nix::sys::stat::Mode::S_IRWXU).unwrap();
+    std::fs::write(directory.join("file"), b"").unwrap();
+    let directory_name = directory.to_str().unwrap();
+
+    let relative = find_fifo("pipe", directory_name);
+    let absolute = find_fifo(directory.join("pipe").to_str().unwrap(), "/nonexistent");
+    let file = find_fifo("file", directory_name);
+    let missing = find_fifo("missing", directory_name);
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    assert_eq!(relative, Some(format!("{}/pipe", directory_name)));
+    assert_eq!(absolute, Some(format!("{}/pipe", directory_name)));
+    assert_eq!(file, None);
+    assert_eq!(missing, None);
+}
+
+#[test]
+fn run_streams_screenshot_into_fifo() {
+    let directory = std::env::temp_dir().join("scrcap-test-fifo");
+    std::fs::create_dir_all(&directory).unwrap();
+    let fifo = directory.join("screenshot");
+    nix::unistd::mkfifo(&fifo, nix::sys::stat::Mode::S_IRWXU).unwrap();
+    let reader = {
+        let fifo = fifo.clone();
+        std::thread::spawn(move || std::fs::read(fifo).unwrap())
+    };
+    let mut platform = MockPlatform::new(dual_outputs());
+
+    cli::run(
+        &args(&[
+            "-o",
+            "DP-1",
+            "--filename",
+            fifo.to_str().unwrap(),
+            "--encoding-format",
+            "ppm",
+        ]),
+        &Config::default(),
+        &mut platform,
+    )
+    .unwrap();
+
+    let data = reader.join().unwrap();
+    let filenames = std::fs::read_dir(&directory).unwrap().count();
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert!(data.starts_with(b"P6\n1920 1080 255\n"));
+    assert_eq!(filenames, 1);
+}
+
+#[test]
+fn run_diff_fails_above_threshold() {
+    let directory = std::env::temp_dir().join("scrcap-test-diff");
+    std::fs::create_dir_all(&directory).unwrap();
+    let baseline_path = directory.join("baseline.png");
+    let mut baseline = common::synthetic_frame(10, 10).to_rgba_image().unwrap();
+    baseline.put_pixel(0, 0, image::Rgba([255, 255, 255, 255]));
+    baseline.save(&baseline_path).unwrap();
+    let diff_path = directory.join("diff.png");
+    let diff_args = |threshold: &str| {
+        args(&[
+            "-o",
+            "DP-1",
+            "-x",
+            "0",
+            "-y",
+            "0",
+            "-w",
+            "10",
+            "-H",
+            "10",
+            "--diff",
+            baseline_path.to_str().unwrap(),
+            "--diff-threshold",
+            threshold,
+            "--diff-output",
+            diff_path.to_str().unwrap(),
+        ])
+    };
+    let mut platform = MockPlatform::new(dual_outputs());
+
+    let exceeded = cli::run(&diff_args("0.5"), &Config::default(), &mut platform).unwrap_err();
+    let allowed = cli::run(&diff_args("1"), &Config::default(), &mut platform);
+    let diff_image = image::open(&diff_path).unwrap().to_rgba8();
+    std::fs::remove_dir_all(&directory).unwrap();
+
+    assert_eq!(exit_code(&exceeded), 8);
+    assert!(allowed.is_ok());
+    assert_eq!(diff_image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+}
+
+#[test]
+fn run_post_processes_png16_frames() {
+    let directory = std::env::temp_dir().join("scrcap-test-png16-post-process");
+    std::fs::create_dir_all(&directory).unwrap();
+    let mut platform = MockPlatform::new(vec![output("DP-1", 0, 0, 200, 100)]);
+
+    cli::run(
+        &args(&[
+            "-o",
+            "DP-1",
+            "-w",
+            "64",
+            "-H",
+            "32",
+            "-e",
+            "png16",
+            "--output-transform",
+            "90",
+            "--crop-to-content",
+            "--also-full",
+            "--directory",
+            directory.to_str().unwrap(),
+            "--filename",
+            "shot",
+        ]),
+        &Config::default(),
+        &mut platform,
+    )
+    .unwrap();
+
+    let image = image::open(directory.join("shot.png")).unwrap();
+    let full_image = image::open(directory.join("shot-full.png")).unwrap();
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(image.color(), image::ColorType::Rgba16);
+    assert_eq!((image.width(), image.height()), (32, 64));
+    assert_eq!(full_image.color(), image::ColorType::Rgba16);
+    assert_eq!((full_image.width(), full_image.height()), (100, 200));
+}
+
+#[test]
+fn run_png16_rejects_region_across_outputs() {
+    let mut platform = MockPlatform::new(dual_outputs());
+    let err = cli::run(
+        &args(&[
+            "-x", "1900", "-y", "0", "-w", "100", "-H", "50", "-e", "png16",
+        ]),
+        &Config::default(),
+        &mut platform,
+    )
+    .unwrap_err();
+
+    assert!(err.to_string().contains("png16"), "{}", err);
+    assert!(err.to_string().contains("DP-1, DP-2"), "{}", err);
+    assert_eq!(exit_code(&err), 4);
+    assert!(platform.captures.is_empty());
+}
+
+#[test]
+fn run_diff_compares_png16_frames() {
+    let directory = std::env::temp_dir().join("scrcap-test-png16-diff");
+    std::fs::create_dir_all(&directory).unwrap();
+    let baseline_path = directory.join("baseline.png");
+    let baseline = common::synthetic_frame(10, 10).to_rgba_image().unwrap();
+    baseline.save(&baseline_path).unwrap();
+    let mut platform = MockPlatform::new(dual_outputs());
+
+    let result = cli::run(
+        &args(&[
+            "-o",
+            "DP-1",
+            "-w",
+            "10",
+            "-H",
+            "10",
+            "-e",
+            "png16",
+            "--diff",
+            baseline_path.to_str().unwrap(),
+            "--diff-threshold",
+            "0",
+        ]),
+        &Config::default(),
+        &mut platform,
+    );
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert!(result.is_ok(), "{:?}", result);
+}
+
+#[test]
+fn diff_threshold_is_a_percentage() {
+    assert!(
+        CmdArgs::try_parse_from(["scrcap", "--diff", "a.png", "--diff-threshold", "101"]).is_err()
+    );
+    assert!(CmdArgs::try_parse_from(["scrcap", "--diff-threshold", "1"]).is_err());
+    assert!(
+        CmdArgs::try_parse_from(["scrcap", "--diff", "a.png", "--diff-threshold", "2.5"]).is_ok()
+    );
+}
+
+#[test]
+fn run_also_saves_full_output_of_region() {
+    let directory = std::env::temp_dir().join("scrcap-test-also-full");
+    std::fs::create_dir_all(&directory).unwrap();
+    let mut platform = MockPlatform::new(vec![
+        output("DP-1", 0, 0, 64, 32),
+        output("DP-2", 64, 0, 32, 16),
+    ]);
+
+    cli::run(
+        &args(&[
+            "-x",
+            "70",
+            "-y",
+            "2",
+            "-w",
+            "10",
+            "-H",
+            "8",
+            "--also-full",
+            "--directory",
+            directory.to_str().unwrap(),
+            "--filename",
+            "shot.ppm",
+        ]),
+        &Config::default(),
+        &mut platform,
+    )
+    .unwrap();
+
+    let region = std::fs::read(directory.join("shot.ppm")).unwrap();
+    let full = std::fs::read(directory.join("shot-full.ppm")).unwrap();
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert!(region.starts_with(b"P6\n10 8 255\n"));
+    assert!(full.starts_with(b"P6\n32 16 255\n"));
+    assert_eq!(
+        platform.captures.last().unwrap(),
+        &("DP-2".to_string(), None)
+    );
+}
+
+#[test]
+fn run_records_changes_until_max_files() {
+    let directory = std::env::temp_dir().join("scrcap-test-since-last-change");
+    std::fs::create_dir_all(&directory).unwrap();
+    let mut platform = MockPlatform::new(dual_outputs());
+
+    cli::run(
+        &args(&[
+            "--since-last-change",
+            "200",
+            "--max-files",
+            "3",
+            "-o",
+            "DP-2",
+            "-x",
+            "1920",
+            "-w",
+            "10",
+            "-H",
+            "10",
+            "--directory",
+            directory.to_str().unwrap(),
+            "--filename",
+            "change",
+            "--encoding-format",
+            "ppm",
+        ]),
+        &Config::default(),
+        &mut platform,
+    )
+    .unwrap();
+
+    let filenames = std::fs::read_dir(&directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(filenames.len(), 3);
+    assert!(filenames
+        .iter()
+        .all(|name| name.starts_with("change-") && name.ends_with(".ppm")));
+    // Every change after the first is captured again at the end of the interval
+    assert_eq!(platform.captures.len(), 5);
+    assert!(platform.captures.iter().all(|(name, _)| name == "DP-2"));
+}
+
+#[test]
+fn session_stats_count_captures_per_format() {
+    let mut stats = SessionStats::default();
+    stats.record(EncodingFormat::Png, 1000, Duration::from_millis(30));
+    stats.record(EncodingFormat::Jpg, 400, Duration::from_millis(10));
+    stats.record(EncodingFormat::Png, 1200, Duration::from_millis(20));
+
+    assert_eq!(stats.captures, 3);
+    assert_eq!(stats.bytes_written, 2600);
+    assert_eq!(stats.average_encode_time(), Duration::from_millis(20));
+    assert_eq!(
+        stats.to_string(),
+        "Captures: 3\nBytes written: 2600\nAverage encode time: 20ms\nFormats: png 2, jpg 1\n"
+    );
+}
+
+#[test]
+fn session_stats_without_captures() {
+    let stats = SessionStats::default();
+    assert_eq!(stats.average_encode_time(), Duration::ZERO);
+    assert!(stats.to_string().starts_with("Captures: 0\n"));
+}
+
+#[test]
+fn timing_requires_json() {
+    assert!(CmdArgs::try_parse_from(["scrcap", "--timing"]).is_err());
+    assert!(CmdArgs::try_parse_from(["scrcap", "--json", "--timing"]).is_ok());
+    assert!(CmdArgs::try_parse_from(["scrcap", "--json", "--data-uri"]).is_err());
+}
+
+#[test]
+fn max_files_requires_since_last_change() {
+    assert!(CmdArgs::try_parse_from(["scrcap", "--max-files", "3"]).is_err());
+    assert!(CmdArgs::try_parse_from(["scrcap", "--since-last-change", "0"]).is_err());
+}
+
+#[test]
+fn region_stream_captures_every_line() {
+    let directory = std::env::temp_dir().join("scrcap-test-region-stdin");
+    std::fs::create_dir_all(&directory).unwrap();
+    let mut platform = MockPlatform::new(dual_outputs());
+    let input = "10,20 30x40\n\n2000,0 100x50\n1900,0 40x10\n";
+
+    run_region_stream(
+        &args(&[
+            "--region-stdin",
+            "--directory",
+            directory.to_str().unwrap(),
+            "--filename",
+            "batch.ppm",
+        ]),
+        &Config::default(),
+        input.as_bytes(),
+        &mut platform,
+    )
+    .unwrap();
+
+    let mut filenames = std::fs::read_dir(&directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    filenames.sort();
+    std::fs::remove_dir_all(&directory).unwrap();
+    assert_eq!(filenames, ["batch-1.ppm", "batch-3.ppm", "batch-4.ppm"]);
+    assert_eq!(
+        platform.captures[..2],
+        [
+            ("DP-1".to_string(), Some(Region::new(10, 20, 30, 40))),
+            ("DP-2".to_string(), Some(Region::new(2000, 0, 100, 50))),
+        ]
+    );
+}
+
+#[test]
+fn region_stream_reports_invalid_line() {
+    let mut platform = MockPlatform::new(dual_outputs());
+    let err = run_region_stream(
+        &args(&["--region-stdin"]),
+        &Config::default(),
+        "0,0 10x10x\n".as_bytes(),
+        &mut platform,
+    )
+    .unwrap_err();
+    assert_eq!(err.to_string(), "Invalid region on line 1");
+    assert!(platform.captures.is_empty());
+}
@@ -0,0 +1,195 @@
+// Not every test binary uses every helper
+#![allow(dead_code)]
+
+use anyhow::{bail, Result};
+use image::ColorType;
+use memmap2::MmapMut;
+use scrcap::platform::{Frame, FrameDescription, FrameFormat, Output, Platform, Region};
+use std::os::fd::RawFd;
+use std::time::Duration;
+
+/// A fake platform that serves configurable outputs and synthetic frames
+#[derive(Default)]
+pub struct MockPlatform {
+    pub outputs: Vec<Output>,
+    pub focused_window: Option<Region>,
+    /// Name of the focused output
+    pub focused_output: Option<String>,
+    /// App id or title of the focused window
+    pub focused_window_name: Option<String>,
+    /// Titles and areas of all windows
+    pub windows: Vec<(String, Region)>,
+    /// Marks and areas of the visible windows carrying them
+    pub marks: Vec<(String, Region)>,
+    /// Names and areas of the visible workspaces
+    pub workspaces: Vec<(String, Region)>,
+    /// Format required by set_required_format. Captures fail unless it is Abgr8888.
+    pub required_format: Option<FrameFormat>,
+    /// Every capture request in the order it was received
+    pub captures: Vec<(String, Option<Region>)>,
+}
+
+impl MockPlatform {
+    pub fn new(outputs: Vec<Output>) -> Self {
+        Self {
+            outputs,
+            ..Default::default()
+        }
+    }
+}
+
+impl Platform for MockPlatform {
+    fn outputs(&self) -> Vec<Output> {
+        self.outputs.clone()
+    }
+
+    fn capture_frame(
+        &mut self,
+        output: &Output,
+        _overlay_cursor: bool,
+        region: Option<Region>,
+    ) -> Result<Frame> {
+        if let Some(required_format) = self.required_format {
+            if required_format != FrameFormat::Abgr8888 {
+                bail!(
+                    "Mock platform does not offer frame format {:?}",
+                    required_format
+                );
+            }
+        }
+        self.captures.push((output.name.clone(), region));
+
+        let (width, height) = match region {
+            Some(region) => (region.width as u32, region.height as u32),
+            None => (output.width as u32, output.height as u32),
+        };
+        Ok(synthetic_frame(width, height))
+    }
+
+    fn capture_frame_raw(
+        &mut self,
+        output: &Output,
+        overlay_cursor: bool,
+        region: Option<Region>,
+    ) -> Result<Frame> {
+        self.capture_frame(output, overlay_cursor, region)
+    }
+
+    fn capture_frame_fd(
+        &mut self,
+        _output: &Output,
+        _overlay_cursor: bool,
+        _region: Option<Region>,
+    ) -> Result<(RawFd, FrameDescription)> {
+        bail!("Capturing into a file descriptor is not supported by the mock platform")
+    }
+
+    fn capture_frame_with_damage(
+        &mut self,
+        output: &Output,
+        overlay_cursor: bool,
+        region: Option<Region>,
+    ) -> Result<Frame> {
+        let mut frame = self.capture_frame(output, overlay_cursor, region)?;
+        frame.damage = vec![Region::new(
+            0,
+            0,
+            frame.frame_format.width as i32,
+            frame.frame_format.height as i32,
+        )];
+        Ok(frame)
+    }
+
+    fn set_required_format(&mut self, format: Option<FrameFormat>) {
+        self.required_format = format;
+    }
+
+    fn focused_output(&self) -> Result<Output> {
+        let Some(name) = self.focused_output.as_ref() else {
+            bail!("The focused output is unknown");
+        };
+        match self.outputs.iter().find(|output| &output.name == name) {
+            Some(output) => Ok(output.clone()),
+            None => bail!("No output named {} found", name),
+        }
+    }
+
+    fn focused_window_area(&self) -> Result<Region> {
+        match self.focused_window {
+            Some(region) => Ok(region),
+            None => bail!("Could not find an active window"),
+        }
+    }
+
+    fn focused_window_name(&self) -> Result<String> {
+        match self.focused_window_name.as_ref() {
+            Some(name) => Ok(name.clone()),
+            None => bail!("Could not find an active window"),
+        }
+    }
+
+    fn wait_for_window(&self, name: &str, _timeout: Duration) -> Result<Region> {
+        match self.windows.iter().find(|(title, _)| title.contains(name)) {
+            Some((_, region)) => Ok(*region),
+            None => bail!("Timed out waiting for a window matching {:?}", name),
+        }
+    }
+
+    fn marked_window_area(&self, mark: &str) -> Result<Region> {
+        match self
+            .marks
+            .iter()
+            .find(|(window_mark, _)| window_mark == mark)
+        {
+            Some((_, region)) => Ok(*region),
+            None => bail!("No window has the mark {:?}", mark),
+        }
+    }
+
+    fn workspace_area(&self, name: &str) -> Result<Region> {
+        match self
+            .workspaces
+            .iter()
+            .find(|(workspace, _)| workspace == name)
+        {
+            Some((_, region)) => Ok(*region),
+            None => bail!("Workspace {} is not visible", name),
+        }
+    }
+}
+
+pub fn output(name: &str, x: i32, y: i32, width: i32, height: i32) -> Output {
+    Output {
+        name: name.into(),
+        x,
+        y,
+        width,
+        height,
+        scale: 1,
+        physical_width: 0,
+        physical_height: 0,
+    }
+}
+
+/// Create a RGBA frame where every pixel encodes its own coordinates
+pub fn synthetic_frame(width: u32, height: u32) -> Frame {
+    let stride = width * 4;
+    let mut frame_mmap = MmapMut::map_anon((stride * height).max(1) as usize).unwrap();
+    for (i, pixel) in frame_mmap.chunks_exact_mut(4).enumerate() {
+        let x = i as u32 % width.max(1);
+        let y = i as u32 / width.max(1);
+        pixel.copy_from_slice(&[x as u8, y as u8, 0, 255]);
+    }
+
+    Frame {
+        frame_format: FrameDescription {
+            format: FrameFormat::Abgr8888,
+            width,
+            height,
+            stride,
+        },
+        frame_mmap,
+        frame_color_type: ColorType::Rgba8,
+        damage: Vec::new(),
+    }
+}
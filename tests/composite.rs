@@ -0,0 +1,36 @@
+mod common;
+
+use common::synthetic_frame;
+use scrcap::platform::{composite_frames, Region};
+
+#[test]
+fn composite_places_parts_at_their_offset() {
+    let region = Region::new(100, 0, 30, 10);
+    let parts = vec![
+        (Region::new(100, 0, 10, 10), synthetic_frame(10, 10)),
+        (Region::new(110, 5, 20, 5), synthetic_frame(20, 5)),
+    ];
+
+    let frame = composite_frames(region, &parts).unwrap();
+    let image = frame.to_rgba_image().unwrap();
+
+    assert_eq!(image.dimensions(), (30, 10));
+    assert_eq!(image.get_pixel(9, 9).0, [9, 9, 0, 255]);
+    assert_eq!(image.get_pixel(12, 6).0, [2, 1, 0, 255]);
+    // Not covered by any part
+    assert_eq!(image.get_pixel(12, 2).0, [0, 0, 0, 0]);
+}
+
+#[test]
+fn composite_upscales_parts_with_lower_scale() {
+    let region = Region::new(0, 0, 20, 10);
+    let parts = vec![
+        (Region::new(0, 0, 10, 10), synthetic_frame(20, 20)),
+        (Region::new(10, 0, 10, 10), synthetic_frame(10, 10)),
+    ];
+
+    let frame = composite_frames(region, &parts).unwrap();
+    assert_eq!(frame.frame_format.width, 40);
+    assert_eq!(frame.frame_format.height, 20);
+    assert_eq!(frame.frame_format.stride, 40 * 4);
+}
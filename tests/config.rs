@@ -0,0 +1,71 @@
+mod common;
+
+use common::output;
+use scrcap::config::Config;
+use scrcap::output::PngCompression;
+use scrcap::platform::Region;
+
+const CONFIG: &str = r#"
+[presets]
+left_half = "0,0 960x1080"
+too_large = "0,0 3840x2160"
+broken = "0,0"
+
+[outputs.DP-1]
+region = "0,0 1920x1050"
+
+[encoding]
+jpeg_quality = 90
+png_compression = "best"
+"#;
+
+#[test]
+fn missing_sections_use_defaults() {
+    let config = Config::parse("").unwrap();
+    assert!(config.presets.is_empty());
+}
+
+#[test]
+fn preset_is_relative_to_output() {
+    let config = Config::parse(CONFIG).unwrap();
+    let region = config
+        .preset("left_half", &output("DP-2", 1920, 0, 2560, 1440))
+        .unwrap();
+    assert_eq!(region, Region::new(1920, 0, 960, 1080));
+}
+
+#[test]
+fn invalid_presets_are_rejected() {
+    let config = Config::parse(CONFIG).unwrap();
+    let output = output("DP-1", 0, 0, 1920, 1080);
+    assert!(config.preset("too_large", &output).is_err());
+    assert!(config.preset("broken", &output).is_err());
+    assert!(config.preset("unknown", &output).is_err());
+}
+
+#[test]
+fn default_region_is_per_output() {
+    let config = Config::parse(CONFIG).unwrap();
+
+    let region = config
+        .default_region(&output("DP-1", 0, 0, 1920, 1080))
+        .unwrap();
+    assert_eq!(region, Some(Region::new(0, 0, 1920, 1050)));
+
+    let region = config
+        .default_region(&output("DP-2", 1920, 0, 2560, 1440))
+        .unwrap();
+    assert_eq!(region, None);
+}
+
+#[test]
+fn encoding_defaults_are_read() {
+    let config = Config::parse(CONFIG).unwrap();
+    assert_eq!(config.encoding.jpeg_quality, Some(90));
+    assert_eq!(config.encoding.png_compression, Some(PngCompression::Best));
+
+    let config = Config::parse("").unwrap();
+    assert_eq!(config.encoding.jpeg_quality, None);
+    assert_eq!(config.encoding.png_compression, None);
+    assert!(Config::parse("[encoding]\npng_compression = \"huge\"").is_err());
+}
@@ -0,0 +1,49 @@
+use scrcap::platform::convert::{convert_rows_inplace, convert_to_rgba16, create_converter};
+use scrcap::platform::FrameFormat;
+
+#[test]
+fn premultiplied_alpha_is_undone() {
+    // Orange at half opacity, premultiplied: 255 * 0.5, 128 * 0.5, 0
+    let mut abgr = [128, 64, 0, 128];
+    create_converter(FrameFormat::Abgr8888).convert_inplace(&mut abgr);
+    assert_eq!(abgr, [255, 128, 0, 128]);
+
+    // The same pixel in ARGB byte order
+    let mut argb = [0, 64, 128, 128];
+    create_converter(FrameFormat::Argb8888).convert_inplace(&mut argb);
+    assert_eq!(argb, [255, 128, 0, 128]);
+}
+
+#[test]
+fn opaque_and_transparent_pixels_are_kept() {
+    let mut data = [10, 20, 30, 255, 0, 0, 0, 0];
+    create_converter(FrameFormat::Abgr8888).convert_inplace(&mut data);
+    assert_eq!(data, [10, 20, 30, 255, 0, 0, 0, 0]);
+
+    // Formats without alpha are never unpremultiplied
+    let mut data = [10, 20, 30, 128];
+    create_converter(FrameFormat::Xbgr8888).convert_inplace(&mut data);
+    assert_eq!(data, [10, 20, 30, 128]);
+}
+
+#[test]
+fn premultiplied_10_bit_alpha_is_undone() {
+    // Red of 341 premultiplied with an alpha of 1/3, i.e. full red
+    let pixel: u32 = (1 << 30) | 341;
+    let rgba16 = convert_to_rgba16(FrameFormat::Abgr2101010, &pixel.to_le_bytes());
+    let samples = rgba16
+        .chunks_exact(2)
+        .map(|sample| u16::from_ne_bytes([sample[0], sample[1]]))
+        .collect::<Vec<_>>();
+    assert_eq!(samples[1..], [0, 0, 0x5555]);
+    assert!(samples[0] >= 0xffc0, "{:#x}", samples[0]);
+}
+
+#[test]
+fn row_padding_is_skipped() {
+    // Two rows of one opaque ARGB pixel, padded to a stride of 6 bytes
+    let mut data = [1, 2, 3, 255, 7, 7, 4, 5, 6, 255, 7, 7];
+    let converter = create_converter(FrameFormat::Xrgb8888);
+    convert_rows_inplace(converter.as_ref(), &mut data, 1, 6);
+    assert_eq!(data, [3, 2, 1, 255, 7, 7, 6, 5, 4, 255, 7, 7]);
+}
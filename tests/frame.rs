@@ -0,0 +1,224 @@
+mod common;
+
+use common::synthetic_frame;
+use image::ColorType;
+use scrcap::output::PostProcess;
+use scrcap::platform::{Frame, FrameDescription, FrameFormat, OutputTransform, Region};
+
+fn pixel(frame: &Frame, x: u32, y: u32) -> &[u8] {
+    let start = (y * frame.frame_format.stride + x * 4) as usize;
+    &frame.frame_mmap[start..start + 4]
+}
+
+#[test]
+fn crop_copies_pixels_at_offset() {
+    let frame = synthetic_frame(64, 32);
+    let cropped = frame.crop(Region::new(10, 5, 20, 8)).unwrap();
+
+    assert_eq!(cropped.frame_format.width, 20);
+    assert_eq!(cropped.frame_format.height, 8);
+    assert_eq!(cropped.frame_format.stride, 20 * 4);
+    assert_eq!(cropped.frame_mmap.len(), 20 * 4 * 8);
+    assert_eq!(pixel(&cropped, 0, 0), &[10, 5, 0, 255]);
+    assert_eq!(pixel(&cropped, 19, 7), &[29, 12, 0, 255]);
+}
+
+#[test]
+fn crop_skips_stride_padding() {
+    let (width, height, stride) = (4u32, 3u32, 24u32);
+    let mut data = vec![0xAA; (stride * height) as usize];
+    for y in 0..height {
+        for x in 0..width {
+            let start = (y * stride + x * 4) as usize;
+            data[start..start + 4].copy_from_slice(&[x as u8, y as u8, 0, 255]);
+        }
+    }
+    let frame = Frame::from_bytes(
+        FrameDescription {
+            format: FrameFormat::Abgr8888,
+            width,
+            height,
+            stride,
+        },
+        ColorType::Rgba8,
+        &data,
+    )
+    .unwrap();
+
+    let cropped = frame.crop(Region::new(1, 1, 3, 2)).unwrap();
+    assert_eq!(cropped.frame_format.stride, 3 * 4);
+    assert!(!cropped.frame_mmap.contains(&0xAA));
+    assert_eq!(pixel(&cropped, 0, 0), &[1, 1, 0, 255]);
+    assert_eq!(pixel(&cropped, 2, 1), &[3, 2, 0, 255]);
+}
+
+#[test]
+fn crop_outside_frame_fails() {
+    let frame = synthetic_frame(64, 32);
+    assert!(frame.crop(Region::new(60, 0, 10, 10)).is_err());
+    assert!(frame.crop(Region::new(0, 0, 0, 10)).is_err());
+}
+
+/// Frame filled with the background color and the content color in the region
+fn framed_content(width: u32, height: u32, background: [u8; 4], content: Region) -> Frame {
+    let mut data = Vec::new();
+    for y in 0..height as i32 {
+        for x in 0..width as i32 {
+            if content.contains(Region::new(x, y, 1, 1)) {
+                data.extend_from_slice(&[200, 10, 10, 255]);
+            } else {
+                data.extend_from_slice(&background);
+            }
+        }
+    }
+    Frame::from_bytes(
+        FrameDescription {
+            format: FrameFormat::Abgr8888,
+            width,
+            height,
+            stride: width * 4,
+        },
+        ColorType::Rgba8,
+        &data,
+    )
+    .unwrap()
+}
+
+fn crop_to_content(frame: Frame, tolerance: u8) -> Frame {
+    let post_process = PostProcess {
+        crop_to_content: Some(tolerance),
+        ..PostProcess::default()
+    };
+    post_process.apply(frame).unwrap()
+}
+
+fn size(frame: &Frame) -> (u32, u32) {
+    (frame.frame_format.width, frame.frame_format.height)
+}
+
+#[test]
+fn crop_to_content_skips_uniform_borders() {
+    let frame = framed_content(20, 12, [30, 30, 30, 255], Region::new(5, 3, 10, 4));
+    let cropped = crop_to_content(frame, 0);
+    assert_eq!(size(&cropped), (10, 4));
+    assert_eq!(pixel(&cropped, 0, 0), &[200, 10, 10, 255]);
+
+    let frame = framed_content(20, 12, [30, 30, 30, 255], Region::new(19, 11, 1, 1));
+    let cropped = crop_to_content(frame, 0);
+    assert_eq!(size(&cropped), (1, 1));
+    assert_eq!(pixel(&cropped, 0, 0), &[200, 10, 10, 255]);
+}
+
+#[test]
+fn crop_to_content_respects_tolerance() {
+    let noisy_frame = || {
+        let mut frame = framed_content(20, 12, [30, 30, 30, 255], Region::new(5, 3, 10, 4));
+        // Slight noise in the border
+        frame.frame_mmap[4 * 20 + 4..4 * 20 + 8].copy_from_slice(&[34, 27, 30, 255]);
+        frame
+    };
+
+    let cropped = crop_to_content(noisy_frame(), 0);
+    assert_eq!(size(&cropped), (14, 6));
+    assert_eq!(pixel(&cropped, 0, 0), &[34, 27, 30, 255]);
+
+    let cropped = crop_to_content(noisy_frame(), 4);
+    assert_eq!(size(&cropped), (10, 4));
+    assert_eq!(pixel(&cropped, 0, 0), &[200, 10, 10, 255]);
+}
+
+#[test]
+fn crop_to_content_keeps_uniform_frame() {
+    let frame = framed_content(8, 8, [30, 30, 30, 255], Region::new(0, 0, 0, 0));
+    assert_eq!(size(&crop_to_content(frame, 0)), (8, 8));
+}
+
+#[test]
+fn resize_to_width_preserves_aspect_ratio() {
+    let frame = synthetic_frame(64, 32);
+    let thumbnail = frame.resize_to_width(16).unwrap();
+
+    assert_eq!(thumbnail.frame_format.width, 16);
+    assert_eq!(thumbnail.frame_format.height, 8);
+    assert_eq!(thumbnail.frame_format.stride, 16 * 4);
+    assert_eq!(thumbnail.frame_mmap.len(), 16 * 4 * 8);
+}
+
+fn transform(frame: Frame, transform: OutputTransform) -> Frame {
+    let post_process = PostProcess {
+        transform: Some(transform),
+        ..PostProcess::default()
+    };
+    post_process.apply(frame).unwrap()
+}
+
+#[test]
+fn transform_rotates_clockwise() {
+    let rotated = transform(synthetic_frame(64, 32), OutputTransform::Rotate90);
+    assert_eq!(rotated.frame_format.width, 32);
+    assert_eq!(rotated.frame_format.height, 64);
+    assert_eq!(pixel(&rotated, 0, 0), &[0, 31, 0, 255]);
+
+    let rotated = transform(synthetic_frame(64, 32), OutputTransform::Rotate270);
+    assert_eq!(pixel(&rotated, 0, 0), &[63, 0, 0, 255]);
+
+    let rotated = transform(synthetic_frame(64, 32), OutputTransform::Rotate180);
+    assert_eq!(rotated.frame_format.width, 64);
+    assert_eq!(pixel(&rotated, 0, 0), &[63, 31, 0, 255]);
+}
+
+#[test]
+fn transform_flips_before_rotating() {
+    let flipped = transform(synthetic_frame(64, 32), OutputTransform::Flipped);
+    assert_eq!(pixel(&flipped, 0, 0), &[63, 0, 0, 255]);
+
+    let flipped = transform(synthetic_frame(64, 32), OutputTransform::Flipped90);
+    assert_eq!(flipped.frame_format.width, 32);
+    assert_eq!(pixel(&flipped, 0, 0), &[63, 31, 0, 255]);
+}
+
+#[test]
+fn output_transform_parses_names() {
+    assert_eq!(
+        "normal".parse::<OutputTransform>().unwrap(),
+        OutputTransform::Normal
+    );
+    assert_eq!(
+        "270".parse::<OutputTransform>().unwrap(),
+        OutputTransform::Rotate270
+    );
+    assert_eq!(
+        "Flipped-180".parse::<OutputTransform>().unwrap(),
+        OutputTransform::Flipped180
+    );
+    assert!("45".parse::<OutputTransform>().is_err());
+}
+
+#[test]
+fn flip_vertically_reverses_rows() {
+    let mut frame = synthetic_frame(4, 3);
+    frame.flip_vertically();
+
+    for y in 0..3 {
+        assert_eq!(pixel(&frame, 1, y), [1, 2 - y as u8, 0, 255]);
+    }
+}
+
+#[test]
+fn flip_vertically_keeps_stride_padding_in_place() {
+    // One pixel per row, padded to a stride of 8 bytes
+    let data = [
+        1, 1, 1, 255, 0xee, 0xee, 0xee, 0xee, 2, 2, 2, 255, 0xee, 0xee, 0xee, 0xee,
+    ];
+    let description = FrameDescription {
+        format: FrameFormat::Abgr8888,
+        width: 1,
+        height: 2,
+        stride: 8,
+    };
+    let mut frame = Frame::from_bytes(description, ColorType::Rgba8, &data).unwrap();
+    frame.flip_vertically();
+
+    assert_eq!(pixel(&frame, 0, 0), [2, 2, 2, 255]);
+    assert_eq!(pixel(&frame, 0, 1), [1, 1, 1, 255]);
+}
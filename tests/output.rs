@@ -0,0 +1,597 @@
+mod common;
+
+use common::synthetic_frame;
+use image::{ColorType, Rgba, RgbaImage};
+use scrcap::output::{
+    diff_images, render_terminal_preview, write_buffer_to_file, write_capture_json, write_to_file,
+    write_to_file_with_options, CaptureReport, ColorProfile, EncodingFormat, EncodingOptions,
+    PngCompression, PostProcess, Watermark, WatermarkPosition, WATERMARK_MARGIN,
+};
+use scrcap::platform::{Frame, FrameDescription, FrameFormat, OutputTransform, Timings};
+use std::time::Duration;
+
+#[test]
+fn terminal_preview_has_requested_columns() {
+    let frame = synthetic_frame(200, 100);
+    let preview = render_terminal_preview(&frame, 40).unwrap();
+
+    let lines = preview.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 10);
+    assert!(lines.iter().all(|line| line.chars().count() == 40));
+}
+
+#[test]
+fn png_round_trips_pixels() {
+    let expected = synthetic_frame(16, 8).to_rgba_image().unwrap();
+    let mut buffer = Vec::new();
+    write_to_file(&mut buffer, EncodingFormat::Png, synthetic_frame(16, 8)).unwrap();
+
+    let image = image::load_from_memory(&buffer).unwrap().to_rgba8();
+    assert_eq!(image, expected);
+}
+
+#[test]
+fn jpeg_round_trips_pixels_approximately() {
+    let expected = synthetic_frame(16, 8).to_rgba_image().unwrap();
+    let mut buffer = Vec::new();
+    write_to_file(&mut buffer, EncodingFormat::Jpg, synthetic_frame(16, 8)).unwrap();
+
+    let image = image::load_from_memory(&buffer).unwrap().to_rgb8();
+    assert_eq!(image.dimensions(), (16, 8));
+    for (pixel, expected) in image.pixels().zip(expected.pixels()) {
+        for channel in 0..3 {
+            let difference = pixel.0[channel].abs_diff(expected.0[channel]);
+            assert!(difference <= 8, "{:?} differs from {:?}", pixel, expected);
+        }
+    }
+}
+
+#[test]
+fn ppm_round_trips_pixels() {
+    let expected = synthetic_frame(16, 8).to_rgba_image().unwrap();
+    let mut buffer = Vec::new();
+    write_to_file(&mut buffer, EncodingFormat::Ppm, synthetic_frame(16, 8)).unwrap();
+
+    let image = image::load_from_memory(&buffer).unwrap().to_rgba8();
+    assert_eq!(image, expected);
+}
+
+#[test]
+fn ppm_drops_alpha() {
+    let mut buffer = Vec::new();
+    write_to_file(&mut buffer, EncodingFormat::Ppm, synthetic_frame(4, 2)).unwrap();
+
+    let image = image::load_from_memory(&buffer).unwrap().to_rgb8();
+    assert_eq!(image.dimensions(), (4, 2));
+    assert_eq!(image.get_pixel(3, 1).0, [3, 1, 0]);
+}
+
+#[test]
+fn padded_rows_are_not_encoded() {
+    // Two pixels per row, padded to a stride of 12 bytes
+    let data = [
+        1, 2, 3, 255, 4, 5, 6, 255, 0xee, 0xee, 0xee, 0xee, //
+        7, 8, 9, 255, 10, 11, 12, 255, 0xee, 0xee, 0xee, 0xee,
+    ];
+    let description = FrameDescription {
+        format: FrameFormat::Abgr8888,
+        width: 2,
+        height: 2,
+        stride: 12,
+    };
+    let frame = Frame::from_bytes(description, ColorType::Rgba8, &data).unwrap();
+
+    let mut buffer = Vec::new();
+    write_to_file(&mut buffer, EncodingFormat::Png, frame).unwrap();
+
+    let image = image::load_from_memory(&buffer).unwrap().to_rgba8();
+    assert_eq!(image.get_pixel(1, 0).0, [4, 5, 6, 255]);
+    assert_eq!(image.get_pixel(0, 1).0, [7, 8, 9, 255]);
+}
+
+#[test]
+fn buffer_is_encoded_without_frame() {
+    let image = RgbaImage::from_pixel(3, 2, Rgba([10, 20, 30, 255]));
+
+    let mut buffer = Vec::new();
+    write_buffer_to_file(
+        &mut buffer,
+        &image,
+        image.width(),
+        image.height(),
+        ColorType::Rgba8,
+        EncodingFormat::Png,
+        EncodingOptions::default(),
+    )
+    .unwrap();
+
+    assert_eq!(image::load_from_memory(&buffer).unwrap().to_rgba8(), image);
+}
+
+#[test]
+fn ppm_keeps_16_bit_samples() {
+    let samples: [u16; 8] = [1023 << 6, 512 << 6, 1, 65535, 0, 65535, 258, 65535];
+    let data = samples
+        .iter()
+        .flat_map(|sample| sample.to_ne_bytes())
+        .collect::<Vec<_>>();
+    let description = FrameDescription {
+        format: FrameFormat::Abgr2101010,
+        width: 2,
+        height: 1,
+        stride: 16,
+    };
+    let frame = Frame::from_bytes(description, ColorType::Rgba16, &data).unwrap();
+
+    let mut buffer = Vec::new();
+    write_to_file(&mut buffer, EncodingFormat::Ppm, frame).unwrap();
+
+    let image = image::load_from_memory(&buffer).unwrap();
+    assert_eq!(image.color(), ColorType::Rgb16);
+    let image = image.to_rgb16();
+    assert_eq!(image.get_pixel(0, 0).0, [1023 << 6, 512 << 6, 1]);
+    assert_eq!(image.get_pixel(1, 0).0, [0, 65535, 258]);
+}
+
+#[test]
+fn ppm_ascii_round_trips_pixels() {
+    let expected = synthetic_frame(16, 8).to_rgba_image().unwrap();
+    let mut buffer = Vec::new();
+    let options = EncodingOptions {
+        ppm_ascii: true,
+        ..Default::default()
+    };
+    write_to_file_with_options(
+        &mut buffer,
+        EncodingFormat::Ppm,
+        synthetic_frame(16, 8),
+        options,
+    )
+    .unwrap();
+
+    assert!(buffer.starts_with(b"P3"));
+    assert!(buffer.is_ascii());
+    let image = image::load_from_memory(&buffer).unwrap().to_rgba8();
+    assert_eq!(image, expected);
+}
+
+#[test]
+fn ppm_ascii_keeps_16_bit_samples() {
+    let samples: [u16; 8] = [1023 << 6, 512 << 6, 1, 65535, 0, 65535, 258, 65535];
+    let data = samples
+        .iter()
+        .flat_map(|sample| sample.to_ne_bytes())
+        .collect::<Vec<_>>();
+    let description = FrameDescription {
+        format: FrameFormat::Abgr2101010,
+        width: 2,
+        height: 1,
+        stride: 16,
+    };
+    let frame = Frame::from_bytes(description, ColorType::Rgba16, &data).unwrap();
+    let options = EncodingOptions {
+        ppm_ascii: true,
+        ..Default::default()
+    };
+
+    let mut buffer = Vec::new();
+    write_to_file_with_options(&mut buffer, EncodingFormat::Ppm, frame, options).unwrap();
+
+    assert_eq!(
+        String::from_utf8(buffer).unwrap(),
+        "P3\n2 1\n65535\n65472 32768 1 0 65535 258\n"
+    );
+}
+
+#[test]
+fn png16_keeps_10_bit_precision() {
+    // Xbgr2101010 pixels with red, green and blue in the lowest bits
+    let pixels: [u32; 2] = [1023 | (513 << 10) | (1 << 20), 0];
+    let data = pixels
+        .iter()
+        .flat_map(|pixel| pixel.to_le_bytes())
+        .collect::<Vec<_>>();
+    let description = FrameDescription {
+        format: FrameFormat::Xbgr2101010,
+        width: 2,
+        height: 1,
+        stride: 8,
+    };
+    let frame = Frame::from_bytes(description, ColorType::Rgba8, &data)
+        .unwrap()
+        .to_rgba16()
+        .unwrap();
+
+    let mut buffer = Vec::new();
+    write_to_file(&mut buffer, EncodingFormat::Png16, frame).unwrap();
+
+    let image = image::load_from_memory(&buffer).unwrap();
+    assert_eq!(image.color(), ColorType::Rgba16);
+    let image = image.to_rgba16();
+    assert_eq!(
+        image.get_pixel(0, 0).0,
+        [65535, (513 << 6) | (513 >> 4), 64, 65535]
+    );
+    assert_eq!(image.get_pixel(1, 0).0, [0, 0, 0, 65535]);
+}
+
+#[test]
+fn watermark_position_is_optional() {
+    let watermark: Watermark = "/logos/c:d.png:top-left".parse().unwrap();
+    assert_eq!(watermark.path.to_str(), Some("/logos/c:d.png"));
+    assert_eq!(watermark.position, WatermarkPosition::TopLeft);
+
+    let watermark: Watermark = "/logos/c:d.png".parse().unwrap();
+    assert_eq!(watermark.path.to_str(), Some("/logos/c:d.png"));
+    assert_eq!(watermark.position, WatermarkPosition::BottomRight);
+}
+
+#[test]
+fn watermark_is_blended_into_corner() {
+    let frame = synthetic_frame(64, 48);
+    let mut watermark = RgbaImage::from_pixel(4, 2, Rgba([255, 255, 255, 255]));
+    watermark.put_pixel(0, 0, Rgba([255, 255, 255, 0]));
+
+    let post_process = PostProcess {
+        watermark: Some((watermark, WatermarkPosition::BottomRight)),
+        ..PostProcess::default()
+    };
+    let frame = post_process.apply(frame).unwrap();
+    let image = frame.to_rgba_image().unwrap();
+
+    let x = 64 - 4 - WATERMARK_MARGIN as u32;
+    let y = 48 - 2 - WATERMARK_MARGIN as u32;
+    // Transparent pixels keep the screenshot
+    assert_eq!(image.get_pixel(x, y).0, [x as u8, y as u8, 0, 255]);
+    assert_eq!(image.get_pixel(x + 3, y + 1).0, [255, 255, 255, 255]);
+    assert_eq!(image.get_pixel(x - 1, y).0, [x as u8 - 1, y as u8, 0, 255]);
+}
+
+#[test]
+fn post_process_rotates_then_crops_then_watermarks() {
+    let mut image = RgbaImage::from_pixel(200, 100, Rgba([40, 40, 40, 255]));
+    for (x, y) in (20..100).flat_map(|x| (40..80).map(move |y| (x, y))) {
+        image.put_pixel(x, y, Rgba([200, 0, 0, 255]));
+    }
+    let frame = Frame::from_rgba_image(FrameFormat::Xbgr8888, &image).unwrap();
+    let post_process = PostProcess {
+        transform: Some(OutputTransform::Rotate90),
+        crop_to_content: Some(0),
+        watermark: Some((
+            RgbaImage::from_pixel(4, 2, Rgba([255, 255, 255, 255])),
+            WatermarkPosition::BottomRight,
+        )),
+    };
+
+    let frame = post_process.apply(frame).unwrap();
+    assert_eq!(
+        (frame.frame_format.width, frame.frame_format.height),
+        (40, 80)
+    );
+    assert_eq!(frame.frame_format.format, FrameFormat::Xbgr8888);
+    let image = frame.to_rgba_image().unwrap();
+    assert_eq!(image.get_pixel(0, 0).0, [200, 0, 0, 255]);
+    // The watermark is in the corner of the cropped image
+    let (x, y) = (
+        40 - 4 - WATERMARK_MARGIN as u32,
+        80 - 2 - WATERMARK_MARGIN as u32,
+    );
+    assert_eq!(image.get_pixel(x, y).0, [255, 255, 255, 255]);
+    assert_eq!(image.get_pixel(x - 1, y).0, [200, 0, 0, 255]);
+}
+
+#[test]
+fn empty_post_process_keeps_frame() {
+    let data = vec![7; 4 * 2 * 8];
+    let frame = Frame::from_bytes(
+        FrameDescription {
+            format: FrameFormat::Xbgr2101010,
+            width: 4,
+            height: 2,
+            stride: 4 * 8,
+        },
+        ColorType::Rgba16,
+        &data,
+    )
+    .unwrap();
+
+    let post_process = PostProcess::default();
+    assert!(post_process.is_empty());
+    let frame = post_process.apply(frame).unwrap();
+    assert_eq!(frame.frame_color_type, ColorType::Rgba16);
+    assert_eq!(&frame.frame_mmap[..], &data[..]);
+}
+
+#[cfg(feature = "progressive-jpeg")]
+#[test]
+fn jpeg_is_encoded_progressively() {
+    let mut buffer = Vec::new();
+    let options = EncodingOptions {
+        progressive: true,
+        ..Default::default()
+    };
+    write_to_file_with_options(
+        &mut buffer,
+        EncodingFormat::Jpg,
+        synthetic_frame(64, 32),
+        options,
+    )
+    .unwrap();
+
+    // Progressive images start with a SOF2 instead of a SOF0 marker
+    assert!(buffer.windows(2).any(|marker| marker == [0xff, 0xc2]));
+    let image = image::load_from_memory(&buffer).unwrap();
+    assert_eq!((image.width(), image.height()), (64, 32));
+}
+
+#[cfg(not(feature = "progressive-jpeg"))]
+#[test]
+fn progressive_jpeg_needs_feature() {
+    let options = EncodingOptions {
+        progressive: true,
+        ..Default::default()
+    };
+    let result = write_to_file_with_options(
+        Vec::new(),
+        EncodingFormat::Jpg,
+        synthetic_frame(4, 4),
+        options,
+    );
+    assert!(result.is_err());
+}
+
+/// Encode the frame with the given options and return the encoded image
+fn encode(format: EncodingFormat, frame: Frame, options: EncodingOptions) -> Vec<u8> {
+    let mut data = Vec::new();
+    write_to_file_with_options(&mut data, format, frame, options).unwrap();
+    data
+}
+
+#[test]
+fn jpeg_quality_changes_size() {
+    let low = encode(
+        EncodingFormat::Jpg,
+        synthetic_frame(64, 64),
+        EncodingOptions {
+            jpeg_quality: Some(10),
+            ..Default::default()
+        },
+    );
+    let high = encode(
+        EncodingFormat::Jpg,
+        synthetic_frame(64, 64),
+        EncodingOptions {
+            jpeg_quality: Some(100),
+            ..Default::default()
+        },
+    );
+    assert!(low.len() < high.len());
+
+    for quality in [0, 101] {
+        let options = EncodingOptions {
+            jpeg_quality: Some(quality),
+            ..Default::default()
+        };
+        let result = write_to_file_with_options(
+            Vec::new(),
+            EncodingFormat::Jpg,
+            synthetic_frame(4, 4),
+            options,
+        );
+        assert!(result.is_err());
+    }
+}
+
+#[test]
+fn png_compression_keeps_pixels() {
+    let expected = synthetic_frame(32, 32).to_rgba_image().unwrap();
+    for compression in [
+        PngCompression::Fast,
+        PngCompression::Default,
+        PngCompression::Best,
+    ] {
+        let png = encode(
+            EncodingFormat::Png,
+            synthetic_frame(32, 32),
+            EncodingOptions {
+                png_compression: Some(compression),
+                ..Default::default()
+            },
+        );
+        let image = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(image, expected);
+    }
+}
+
+#[test]
+fn png_stores_pixel_density() {
+    let mut buffer = Vec::new();
+    let options = EncodingOptions {
+        pixels_per_meter: Some((6400, 6352)),
+        color_profile: Some(ColorProfile::Srgb),
+        ..Default::default()
+    };
+    write_to_file_with_options(
+        &mut buffer,
+        EncodingFormat::Png,
+        synthetic_frame(4, 4),
+        options,
+    )
+    .unwrap();
+
+    assert_eq!(&png_chunk_types(&buffer)[..3], ["IHDR", "sRGB", "pHYs"]);
+    let phys = buffer
+        .windows(4)
+        .position(|window| window == b"pHYs")
+        .unwrap();
+    assert_eq!(
+        &buffer[phys + 4..phys + 13],
+        [0, 0, 0x19, 0, 0, 0, 0x18, 0xd0, 1]
+    );
+    image::load_from_memory(&buffer).unwrap();
+}
+
+#[test]
+fn png_stores_metadata_as_text() {
+    let mut buffer = Vec::new();
+    let options = EncodingOptions {
+        metadata: vec![
+            ("Software".into(), "scrcap".into()),
+            ("Compositor".into(), "sway 1.8 ✓".into()),
+        ],
+        pixels_per_meter: Some((6400, 6352)),
+        ..Default::default()
+    };
+    write_to_file_with_options(
+        &mut buffer,
+        EncodingFormat::Png,
+        synthetic_frame(4, 4),
+        options,
+    )
+    .unwrap();
+
+    assert_eq!(
+        &png_chunk_types(&buffer)[..4],
+        ["IHDR", "pHYs", "tEXt", "tEXt"]
+    );
+    let text = buffer
+        .windows(4)
+        .position(|window| window == b"tEXt")
+        .unwrap();
+    assert_eq!(&buffer[text + 4..text + 19], b"Software\0scrcap");
+    let compositor = b"Compositor\0sway 1.8 ?";
+    assert!(buffer
+        .windows(compositor.len())
+        .any(|window| window == compositor));
+    image::load_from_memory(&buffer).unwrap();
+}
+
+#[test]
+fn png_metadata_keyword_must_not_be_empty() {
+    let options = EncodingOptions {
+        metadata: vec![(String::new(), "text".into())],
+        ..Default::default()
+    };
+    assert!(write_to_file_with_options(
+        Vec::new(),
+        EncodingFormat::Png,
+        synthetic_frame(4, 4),
+        options
+    )
+    .is_err());
+}
+
+fn capture_report(path: &str, timings: Option<Timings>) -> CaptureReport {
+    CaptureReport {
+        path: path.into(),
+        width: 1920,
+        height: 1080,
+        format: FrameFormat::Xrgb8888,
+        bytes: 123456,
+        timings,
+    }
+}
+
+#[test]
+fn capture_json_describes_screenshot() {
+    let mut buffer = Vec::new();
+    write_capture_json(&mut buffer, &capture_report("/tmp/a \"b\".png", None)).unwrap();
+
+    assert_eq!(
+        String::from_utf8(buffer).unwrap(),
+        r#"{"path":"/tmp/a \"b\".png","width":1920,"height":1080,"format":"Xrgb8888","bytes":123456}"#
+            .to_string()
+            + "\n"
+    );
+}
+
+#[test]
+fn capture_json_includes_timings_in_milliseconds() {
+    let timings = Timings {
+        connect: Duration::from_micros(2500),
+        discover_outputs: Duration::from_millis(1),
+        capture: Duration::from_millis(16),
+        convert: Duration::from_micros(4250),
+        encode: Duration::from_millis(80),
+    };
+    let mut buffer = Vec::new();
+    write_capture_json(&mut buffer, &capture_report("shot.png", Some(timings))).unwrap();
+
+    let json = String::from_utf8(buffer).unwrap();
+    assert!(json.ends_with(
+        r#""timings":{"connect":2.5,"discover_outputs":1.0,"capture":16.0,"convert":4.25,"encode":80.0}}
+"#
+    ));
+}
+
+#[test]
+fn diff_counts_differing_pixels() {
+    let baseline = synthetic_frame(10, 4).to_rgba_image().unwrap();
+    let mut image = baseline.clone();
+    image.put_pixel(3, 1, Rgba([255, 255, 255, 255]));
+    image.put_pixel(9, 3, Rgba([9, 3, 0, 254]));
+
+    let diff = diff_images(&image, &baseline).unwrap();
+    assert_eq!(diff.differing_pixels, 2);
+    assert_eq!(diff.total_pixels, 40);
+    assert_eq!(diff.percentage(), 5.0);
+    assert_eq!(diff.diff_image.get_pixel(3, 1).0, [255, 0, 0, 255]);
+    let unchanged = diff.diff_image.get_pixel(0, 0).0;
+    assert_eq!(unchanged[0], unchanged[1]);
+    assert_ne!(unchanged, [255, 0, 0, 255]);
+}
+
+#[test]
+fn diff_of_identical_images_is_empty() {
+    let image = synthetic_frame(10, 4).to_rgba_image().unwrap();
+    let diff = diff_images(&image, &image).unwrap();
+    assert_eq!(diff.differing_pixels, 0);
+    assert_eq!(diff.percentage(), 0.0);
+}
+
+#[test]
+fn diff_needs_images_of_same_size() {
+    let image = synthetic_frame(10, 4).to_rgba_image().unwrap();
+    let baseline = synthetic_frame(4, 10).to_rgba_image().unwrap();
+    assert!(diff_images(&image, &baseline).is_err());
+}
+
+/// Types of the chunks of a PNG image in order
+fn png_chunk_types(png: &[u8]) -> Vec<String> {
+    let mut types = Vec::new();
+    let mut offset = 8;
+    while offset + 8 <= png.len() {
+        let length = u32::from_be_bytes(png[offset..offset + 4].try_into().unwrap()) as usize;
+        types.push(String::from_utf8_lossy(&png[offset + 4..offset + 8]).into_owned());
+        offset += length + 12;
+    }
+    types
+}
+
+#[test]
+fn png_is_tagged_with_color_profile() {
+    let profile_path = std::env::temp_dir().join("scrcap-test-profile.icc");
+    std::fs::write(&profile_path, b"not a real profile").unwrap();
+
+    for (color_profile, chunk_type) in [
+        (ColorProfile::Srgb, "sRGB"),
+        (ColorProfile::Icc(profile_path.clone()), "iCCP"),
+    ] {
+        let mut buffer = Vec::new();
+        let options = EncodingOptions {
+            color_profile: Some(color_profile),
+            ..Default::default()
+        };
+        write_to_file_with_options(
+            &mut buffer,
+            EncodingFormat::Png,
+            synthetic_frame(4, 4),
+            options,
+        )
+        .unwrap();
+
+        assert_eq!(&png_chunk_types(&buffer)[..2], ["IHDR", chunk_type]);
+        image::load_from_memory(&buffer).unwrap();
+    }
+    std::fs::remove_file(profile_path).unwrap();
+}
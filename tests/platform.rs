@@ -0,0 +1,148 @@
+mod common;
+
+use common::{output, MockPlatform};
+use scrcap::platform::{
+    capture_stream, create_platform, output_at_point, outputs, Output, Platform, Region,
+    SessionInfo, VersionInfo,
+};
+use std::time::{Duration, Instant};
+
+#[test]
+#[ignore = "needs a running compositor that implements wlr-screencopy"]
+fn create_and_drop_many_platforms() {
+    for _ in 0..200 {
+        let platform = create_platform().unwrap();
+        assert!(!platform.outputs().is_empty());
+    }
+}
+
+#[test]
+#[ignore = "needs a running compositor that implements wlr-screencopy"]
+fn outputs_can_be_listed_from_another_thread() {
+    let outputs = std::thread::spawn(|| outputs().unwrap()).join().unwrap();
+    assert!(!outputs.is_empty());
+}
+
+#[test]
+fn outputs_can_be_sent_between_threads() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Vec<Output>>();
+}
+
+#[test]
+fn capture_stream_passes_every_frame_to_callback() {
+    let output = output("DP-1", 0, 0, 64, 32);
+    let mut platform = MockPlatform::new(vec![output.clone()]);
+    let mut sizes = Vec::new();
+
+    let start = Instant::now();
+    capture_stream(
+        &mut platform,
+        &output,
+        Some(Region::new(0, 0, 16, 8)),
+        Duration::from_millis(20),
+        3,
+        |frame| {
+            sizes.push((frame.frame_format.width, frame.frame_format.height));
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    assert_eq!(sizes, [(16, 8); 3]);
+    assert_eq!(platform.captures.len(), 3);
+    // No wait after the last frame
+    assert!(start.elapsed() >= Duration::from_millis(40));
+}
+
+#[test]
+fn capture_stream_stops_on_callback_error() {
+    let output = output("DP-1", 0, 0, 64, 32);
+    let mut platform = MockPlatform::new(vec![output.clone()]);
+
+    let result = capture_stream(&mut platform, &output, None, Duration::ZERO, 5, |_| {
+        anyhow::bail!("Enough")
+    });
+
+    assert!(result.is_err());
+    assert_eq!(platform.captures.len(), 1);
+}
+
+#[test]
+fn output_contains_point_excludes_far_edges() {
+    let output = output("DP-2", 1920, 0, 2560, 1440);
+    assert!(output.contains_point(1920, 0));
+    assert!(output.contains_point(4479, 1439));
+    assert!(!output.contains_point(4480, 0));
+    assert!(!output.contains_point(1919, 0));
+    assert!(!output.contains_point(2000, 1440));
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn async_capture_creates_platform_on_blocking_thread() {
+    use scrcap::platform::capture_frame_async_with;
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let frame = runtime
+        .block_on(capture_frame_async_with(
+            || {
+                let outputs = vec![output("DP-1", 0, 0, 64, 32)];
+                Ok(Box::new(MockPlatform::new(outputs)) as Box<dyn Platform>)
+            },
+            output("DP-1", 0, 0, 64, 32),
+            false,
+            Some(Region::new(0, 0, 16, 8)),
+        ))
+        .unwrap();
+
+    assert_eq!(
+        (frame.frame_format.width, frame.frame_format.height),
+        (16, 8)
+    );
+}
+
+#[test]
+fn output_at_point_finds_containing_output() {
+    let outputs = vec![
+        output("DP-1", 0, 0, 1920, 1080),
+        output("DP-2", 1920, 0, 2560, 1440),
+    ];
+    assert_eq!(output_at_point(&outputs, 0, 0).unwrap().name, "DP-1");
+    assert_eq!(output_at_point(&outputs, 1920, 1079).unwrap().name, "DP-2");
+    assert!(output_at_point(&outputs, 1000, 1080).is_none());
+    assert!(output_at_point(&outputs, -1, 0).is_none());
+}
+
+#[test]
+fn session_info_metadata_skips_unknown_entries() {
+    let session_info = SessionInfo {
+        compositor: Some("sway 1.8.1".into()),
+        wayland_socket: None,
+    };
+    let metadata = session_info.metadata();
+
+    assert_eq!(metadata.len(), 2);
+    assert_eq!(metadata[0].0, "Software");
+    assert!(metadata[0].1.starts_with("scrcap "));
+    assert_eq!(metadata[1], ("Compositor".into(), "sway 1.8.1".into()));
+}
+
+#[test]
+fn version_info_lists_protocols() {
+    let version_info = VersionInfo {
+        screencopy: Some(3),
+        xdg_output: None,
+        wl_shm: Some(1),
+        swayipc: true,
+    };
+    let report = version_info.to_string();
+
+    assert!(report.starts_with(&format!("scrcap {}\n", env!("CARGO_PKG_VERSION"))));
+    assert!(report.contains("zwlr_screencopy_manager_v1: version 3\n"));
+    assert!(report.contains("zxdg_output_manager_v1: not available\n"));
+    assert!(report.contains("wl_shm: version 1\n"));
+    assert!(report.ends_with("swayipc: available\n"));
+}
@@ -0,0 +1,82 @@
+use scrcap::platform::{Output, Region};
+
+#[test]
+fn overlapping_regions_intersect() {
+    let a = Region::new(0, 0, 100, 100);
+    let b = Region::new(50, 80, 100, 100);
+    assert!(a.intersects(b));
+    assert!(b.intersects(a));
+    assert_eq!(a.intersection(b), Some(Region::new(50, 80, 50, 20)));
+}
+
+#[test]
+fn touching_regions_do_not_intersect() {
+    let a = Region::new(0, 0, 1920, 1080);
+    let b = Region::new(1920, 0, 2560, 1440);
+    assert!(!a.intersects(b));
+    assert_eq!(a.intersection(b), None);
+}
+
+#[test]
+fn disjoint_regions_do_not_intersect() {
+    let a = Region::new(0, 0, 10, 10);
+    let b = Region::new(-50, 200, 10, 10);
+    assert!(!a.intersects(b));
+}
+
+#[test]
+fn area_does_not_overflow() {
+    assert_eq!(Region::new(0, 0, 7680, 4320).area(), 33_177_600);
+    assert_eq!(
+        Region::new(0, 0, i32::MAX, i32::MAX).area(),
+        i32::MAX as i64 * i32::MAX as i64
+    );
+}
+
+#[test]
+fn area_of_empty_region_is_zero() {
+    assert_eq!(Region::new(10, 10, 0, 100).area(), 0);
+    assert_eq!(Region::new(10, 10, -5, 100).area(), 0);
+}
+
+#[test]
+fn geometry_is_parsed() {
+    let region: Region = "10,-20 1920x1080".parse().unwrap();
+    assert_eq!(region, Region::new(10, -20, 1920, 1080));
+}
+
+#[test]
+fn invalid_geometry_is_rejected() {
+    assert!("10,20".parse::<Region>().is_err());
+    assert!("10 20x30".parse::<Region>().is_err());
+    assert!("a,b 1x1".parse::<Region>().is_err());
+}
+
+#[test]
+fn pixel_density_follows_physical_size() {
+    // A 27" 4K panel at a scale of 2
+    let output = Output {
+        width: 1920,
+        height: 1080,
+        scale: 2,
+        physical_width: 600,
+        physical_height: 340,
+        ..Output::default()
+    };
+    assert_eq!(output.pixels_per_meter(), Some((6400, 6352)));
+
+    // Rotated by 90 degrees, the panel reports its unrotated size
+    let rotated = Output {
+        width: 1080,
+        height: 1920,
+        ..output.clone()
+    };
+    assert_eq!(rotated.pixels_per_meter(), Some((6352, 6400)));
+
+    let unknown = Output {
+        physical_width: 0,
+        physical_height: 0,
+        ..output
+    };
+    assert_eq!(unknown.pixels_per_meter(), None);
+}